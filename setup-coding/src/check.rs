@@ -0,0 +1,318 @@
+use crate::config::{State, TargetEnvironment, ToolTarget};
+use crate::error::Error;
+use crate::facts::Facts;
+use crate::graph::{plan, Prerequisites, Target};
+use crate::keys::get_ssh_key_path;
+use crate::progress;
+use crate::registry::Registry;
+use crate::report::{Category, Outcome};
+use crate::runner::Runner;
+use crate::selection::{get_category_name, Selection};
+use crate::state_file::{StateFile, ToolRecord};
+use crate::tools::{self, get_tool_settings};
+use crate::updates::get_dependency_packages;
+use serde_derive::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Problem {
+    Missing,
+    WrongVersion,
+    NotAbsent,
+    Invalid,
+}
+
+/// One entry of the target environment that the system does not satisfy.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Drift {
+    pub category: Category,
+    pub name: String,
+    pub problem: Problem,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub found: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Drift {
+    fn new(category: Category, name: &str, problem: Problem) -> Drift {
+        Drift {
+            category,
+            name: name.to_string(),
+            problem,
+            expected: None,
+            found: None,
+            detail: None,
+        }
+    }
+}
+
+/// What the state file says setup-coding did to a tool, to tell drift from its own installs
+/// apart from tools the user installed or removed by hand.
+fn describe_record(record: Option<&ToolRecord>) -> String {
+    match record {
+        None => "not installed by setup-coding".to_string(),
+        Some(record) => format!(
+            "setup-coding installed {} with {} on {}",
+            record.version.as_deref().unwrap_or("it"),
+            record.method,
+            record.installed_at
+        ),
+    }
+}
+
+fn check_tool(
+    runner: &mut dyn Runner,
+    registry: &Registry,
+    name: &str,
+    tool_target: ToolTarget,
+    record: Option<&ToolRecord>,
+) -> Result<Option<Drift>, Error> {
+    let recipe = registry
+        .get(name)
+        .ok_or_else(|| Error::InvalidConfig(format!("unknown tool: {name}")))?;
+    let settings = get_tool_settings(name, tool_target)?;
+
+    if settings.state == State::Absent {
+        if !recipe.find(runner, None)? {
+            return Ok(None);
+        }
+
+        let mut drift = Drift::new(Category::Tool, name, Problem::NotAbsent);
+        drift.found = recipe
+            .installed_version(runner)?
+            .map(|version| version.to_string());
+        drift.detail = Some(describe_record(record));
+
+        return Ok(Some(drift));
+    }
+
+    if recipe.find(runner, settings.version.as_ref())? {
+        return Ok(None);
+    }
+
+    let found = match settings.version {
+        None => None,
+        Some(_) => recipe
+            .installed_version(runner)?
+            .map(|version| version.to_string()),
+    };
+
+    let mut drift = match found {
+        None => Drift::new(Category::Tool, name, Problem::Missing),
+        Some(_) => Drift::new(Category::Tool, name, Problem::WrongVersion),
+    };
+    drift.expected = settings.version.map(|version| version.to_string());
+    drift.found = found;
+    // a missing tool is only worth explaining when setup-coding put it there
+    drift.detail = match (drift.problem, record) {
+        (Problem::Missing, None) => None,
+        _ => Some(describe_record(record)),
+    };
+
+    Ok(Some(drift))
+}
+
+pub(crate) fn check_dependencies(
+    runner: &mut dyn Runner,
+    facts: &Facts,
+) -> Result<Option<Drift>, Error> {
+    let package_manager = facts.package_manager()?;
+    let mut missing = Vec::new();
+
+    for package in get_dependency_packages(package_manager) {
+        let installed = match runner.query(&package_manager.query_installed(package)) {
+            Err(error) if error.is_not_found() => false,
+            result => result?.status.is_success(),
+        };
+
+        if !installed {
+            missing.push(package);
+        }
+    }
+
+    if missing.is_empty() {
+        return Ok(None);
+    }
+
+    let mut drift = Drift::new(Category::Update, "dependencies", Problem::Missing);
+    drift.detail = Some(format!("missing packages: {}", missing.join(" ")));
+
+    Ok(Some(drift))
+}
+
+/// Compares the system against the target environment using only read-only queries.
+///
+/// Only the `dependencies` update leaves something to check; `system` and `cleanup` are
+/// actions rather than states. Prerequisites that the config does not name are not checked,
+/// they are only set up for the entries that need them.
+///
+/// The system is always probed, since the state file only says what was true after the last
+/// run; what it records is added to the details of tool drift.
+pub fn check(
+    runner: &mut dyn Runner,
+    facts: &Facts,
+    state_file: &StateFile,
+    target_environment: TargetEnvironment,
+    selection: &Selection,
+) -> Result<Vec<Drift>, Error> {
+    let mut registry = Registry::builtin()?;
+    let mut tools = target_environment.tools;
+
+    tools::add_custom_tools(&mut registry, &mut tools, target_environment.custom_tools)?;
+
+    let plan = plan(
+        &registry,
+        facts,
+        selection,
+        target_environment.updates,
+        tools,
+        target_environment.keys,
+        Prerequisites::Omit,
+    )?;
+    let mut drifts = Vec::new();
+
+    // entries that do not apply to this machine are no drift, unless that cannot be told
+    for excluded in plan.excluded {
+        match excluded.outcome {
+            Outcome::Failed(detail) => {
+                let mut drift = Drift::new(excluded.category, &excluded.name, Problem::Invalid);
+                drift.detail = Some(detail);
+                drifts.push(drift);
+            }
+            Outcome::Skipped(reason) => {
+                progress!("skipped {}: {}: {reason}", excluded.category, excluded.name);
+            }
+            Outcome::Succeeded(_) => {}
+        }
+    }
+
+    let mut category = None;
+
+    for step in plan.steps {
+        if category != Some(step.node.category) {
+            progress!("\nchecking {}", get_category_name(step.node.category));
+
+            category = Some(step.node.category);
+        }
+
+        let name = &step.node.name;
+
+        match step.target {
+            Target::Update(true) if name == "dependencies" => {
+                match check_dependencies(runner, facts) {
+                    Ok(drift) => drifts.extend(drift),
+                    Err(error) => {
+                        let mut drift = Drift::new(Category::Update, name, Problem::Invalid);
+                        drift.detail = Some(error.to_string());
+                        drifts.push(drift);
+                    }
+                }
+            }
+            Target::Update(_) => {}
+            Target::Tool(tool_target) => {
+                let record = state_file.get(name);
+
+                match check_tool(runner, &registry, name, tool_target, record) {
+                    Ok(drift) => drifts.extend(drift),
+                    Err(error) => {
+                        let mut drift = Drift::new(Category::Tool, name, Problem::Invalid);
+                        drift.detail = Some(error.to_string());
+                        drifts.push(drift);
+                    }
+                }
+            }
+            Target::Ssh(ssh) => match get_ssh_key_path(&ssh.algorithm) {
+                Ok(ssh_key_path) if ssh_key_path.exists() => {}
+                Ok(ssh_key_path) => {
+                    let mut drift = Drift::new(Category::Key, name, Problem::Missing);
+                    drift.expected = Some(ssh_key_path.display().to_string());
+                    drifts.push(drift);
+                }
+                Err(error) => {
+                    let mut drift = Drift::new(Category::Key, name, Problem::Invalid);
+                    drift.detail = Some(error.to_string());
+                    drifts.push(drift);
+                }
+            },
+        }
+    }
+
+    Ok(drifts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::facts::DEBIAN;
+    use crate::runner::ScriptedRunner;
+
+    #[test]
+    fn tool_drift_says_what_setup_coding_installed() {
+        let facts = Facts::fixture(DEBIAN);
+        let mut runner = ScriptedRunner::new().on_query("node --version", "v18.0.1\n");
+        let mut state_file = StateFile::empty();
+        let target_environment = toml::from_str::<TargetEnvironment>(
+            r#"
+[tools]
+gh = "latest"
+git = "latest"
+node = "absent"
+"#,
+        )
+        .unwrap();
+
+        state_file.insert(
+            "git",
+            ToolRecord {
+                method: "apt".to_string(),
+                version: Some("2.39.2".to_string()),
+                installed_at: "2022-05-01T12:30:00Z".to_string(),
+                checked_at: "2022-05-01T12:30:00Z".to_string(),
+                ..ToolRecord::default()
+            },
+        );
+
+        let drifts = check(
+            &mut runner,
+            &facts,
+            &state_file,
+            target_environment,
+            &Selection::default(),
+        )
+        .unwrap();
+        let summary = drifts
+            .iter()
+            .map(|drift| {
+                (
+                    drift.name.as_str(),
+                    drift.problem,
+                    drift.found.as_deref(),
+                    drift.detail.as_deref(),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // gh was never installed by setup-coding, so there is nothing more to say about it
+        assert_eq!(
+            summary,
+            [
+                ("gh", Problem::Missing, None, None),
+                (
+                    "git",
+                    Problem::Missing,
+                    None,
+                    Some("setup-coding installed 2.39.2 with apt on 2022-05-01T12:30:00Z")
+                ),
+                (
+                    "node",
+                    Problem::NotAbsent,
+                    Some("18.0.1"),
+                    Some("not installed by setup-coding")
+                ),
+            ]
+        );
+    }
+}
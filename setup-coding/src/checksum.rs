@@ -0,0 +1,90 @@
+use crate::error::Error;
+use crate::progress;
+use crate::runner::{CommandLine, Runner};
+use std::path::Path;
+
+/// Lowercase hex, the way sha256sum prints digests.
+pub fn normalize_sha256(sha256: &str) -> String {
+    sha256.trim().to_lowercase()
+}
+
+/// Checks a downloaded file against the expected SHA-256 digest.
+pub fn verify_sha256(
+    runner: &mut dyn Runner,
+    path: &Path,
+    url: &str,
+    expected: &str,
+) -> Result<(), Error> {
+    let expected = normalize_sha256(expected);
+
+    // sha256sum /tmp/setup-coding-a1B2c3/codecov
+    let command = CommandLine::new("sha256sum").arg(path.to_string_lossy());
+
+    let output = match runner.verify(&command, &expected)? {
+        None => return Ok(()),
+        Some(output) => output,
+    };
+
+    if !output.status.is_success() {
+        return Err(Error::NonZeroExit {
+            command: command.to_string(),
+            status: output.status,
+        });
+    }
+
+    // sha256sum prints `<digest>  <path>`
+    let found = output
+        .stdout
+        .split_whitespace()
+        .next()
+        .map(normalize_sha256)
+        .unwrap_or_default();
+
+    if found != expected {
+        return Err(Error::ChecksumMismatch {
+            url: url.to_string(),
+            expected,
+            found,
+        });
+    }
+
+    progress!("verified sha256: {found}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::SystemRunner;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    /// The SHA-256 of `abc`, from FIPS 180-2.
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn verify_sha256_compares_the_digest_of_the_file() {
+        let path = env::temp_dir().join(format!("setup-coding-checksum-{}", process::id()));
+
+        fs::write(&path, "abc").unwrap();
+
+        let url = "https://example.com/abc";
+        let matches = verify_sha256(&mut SystemRunner, &path, url, ABC_SHA256);
+        let uppercase = verify_sha256(&mut SystemRunner, &path, url, &ABC_SHA256.to_uppercase());
+        let other = verify_sha256(&mut SystemRunner, &path, url, &"0".repeat(64));
+
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches.is_ok(), "{matches:?}");
+        assert!(uppercase.is_ok(), "{uppercase:?}");
+        assert_eq!(
+            other.unwrap_err().to_string(),
+            format!(
+                "the download from {url} has sha256 {ABC_SHA256} instead of the expected {}",
+                "0".repeat(64)
+            )
+        );
+    }
+}
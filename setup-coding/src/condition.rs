@@ -0,0 +1,657 @@
+use crate::config::{did_you_mean, Keys, ToolTarget, Updates};
+use crate::error::Error;
+use crate::facts::Facts;
+use crate::report::{Category, Outcome};
+use schemars::{json_schema, JsonSchema, Schema, SchemaGenerator};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// The facts a condition can compare against a quoted string, e.g. `arch == 'amd64'`.
+pub(crate) const STRING_FACTS: &[&str] = &[
+    "arch",
+    "hostname",
+    "id",
+    "kernel",
+    "machine",
+    "package_manager",
+    "release",
+];
+
+/// The facts a condition can use on their own, e.g. `has_display`.
+const BOOLEAN_FACTS: &[&str] = &["has_display"];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Identifier(String),
+    String(String),
+    Equal,
+    NotEqual,
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Identifier(name) => write!(f, "`{name}`"),
+            Token::String(value) => write!(f, "'{value}'"),
+            Token::Equal => write!(f, "`==`"),
+            Token::NotEqual => write!(f, "`!=`"),
+            Token::And => write!(f, "`&&`"),
+            Token::Or => write!(f, "`||`"),
+            Token::Not => write!(f, "`!`"),
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '=' if chars.next_if_eq(&'=').is_some() => Token::Equal,
+            '!' if chars.next_if_eq(&'=').is_some() => Token::NotEqual,
+            '!' => Token::Not,
+            '&' if chars.next_if_eq(&'&').is_some() => Token::And,
+            '|' if chars.next_if_eq(&'|').is_some() => Token::Or,
+            '\'' | '"' => {
+                let mut value = String::new();
+
+                loop {
+                    match chars.next() {
+                        None => return Err(format!("unterminated string {c}{value}")),
+                        Some(end) if end == c => break,
+                        Some(other) => value.push(other),
+                    }
+                }
+
+                Token::String(value)
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut name = c.to_string();
+
+                while let Some(next) =
+                    chars.next_if(|next| next.is_ascii_alphanumeric() || *next == '_')
+                {
+                    name.push(next);
+                }
+
+                Token::Identifier(name)
+            }
+            c => return Err(format!("unexpected `{c}`")),
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expression {
+    Fact(String),
+    Compare {
+        fact: String,
+        value: String,
+        equal: bool,
+    },
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+}
+
+/// Recursive descent over `||`, then `&&`, then `!`, comparisons and parentheses.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Expression, String> {
+        let mut expression = self.and()?;
+
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+
+        Ok(expression)
+    }
+
+    fn and(&mut self) -> Result<Expression, String> {
+        let mut expression = self.unary()?;
+
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expression = Expression::And(Box::new(expression), Box::new(self.unary()?));
+        }
+
+        Ok(expression)
+    }
+
+    fn unary(&mut self) -> Result<Expression, String> {
+        match self.next() {
+            None => Err("unexpected end".to_string()),
+            Some(Token::Not) => Ok(Expression::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let expression = self.or()?;
+
+                match self.next() {
+                    Some(Token::Close) => Ok(expression),
+                    Some(token) => Err(format!("expected `)`, found {token}")),
+                    None => Err("expected `)`".to_string()),
+                }
+            }
+            Some(Token::Identifier(fact)) => self.fact(fact),
+            Some(token) => Err(format!("expected a fact, found {token}")),
+        }
+    }
+
+    fn fact(&mut self, fact: String) -> Result<Expression, String> {
+        let equal = match self.peek() {
+            Some(Token::Equal) => true,
+            Some(Token::NotEqual) => false,
+            _ if BOOLEAN_FACTS.contains(&fact.as_str()) => return Ok(Expression::Fact(fact)),
+            _ if STRING_FACTS.contains(&fact.as_str()) => {
+                return Err(format!("expected `==` or `!=` after `{fact}`"))
+            }
+            _ => return Err(unknown_fact(&fact, BOOLEAN_FACTS)),
+        };
+
+        if BOOLEAN_FACTS.contains(&fact.as_str()) {
+            return Err(format!(
+                "`{fact}` is true or false by itself, it cannot be compared"
+            ));
+        }
+
+        if !STRING_FACTS.contains(&fact.as_str()) {
+            return Err(unknown_fact(&fact, STRING_FACTS));
+        }
+
+        self.next();
+
+        match self.next() {
+            Some(Token::String(value)) => Ok(Expression::Compare { fact, value, equal }),
+            Some(token) => Err(format!("expected a quoted string, found {token}")),
+            None => Err("expected a quoted string".to_string()),
+        }
+    }
+}
+
+fn unknown_fact(fact: &str, expected: &[&str]) -> String {
+    let known = STRING_FACTS.iter().chain(BOOLEAN_FACTS).copied();
+
+    match did_you_mean(fact, known) {
+        suggestion if !suggestion.is_empty() => format!("unknown fact `{fact}`{suggestion}"),
+        _ => format!("expected one of {}, found `{fact}`", expected.join(", ")),
+    }
+}
+
+pub(crate) fn get_string_fact(facts: &Facts, fact: &str) -> Result<String, Error> {
+    match fact {
+        "arch" => Ok(facts.deb_architecture()?.to_string()),
+        "hostname" => Ok(facts.hostname()?.to_string()),
+        "id" => Ok(facts.id()?.to_string()),
+        "kernel" => Ok(facts.kernel_name()?.to_string()),
+        "machine" => Ok(facts.machine_hardware_name()?.to_string()),
+        "package_manager" => Ok(facts.package_manager()?.to_string()),
+        "release" => Ok(facts.version_codename()?.to_string()),
+        _ => unreachable!("facts are checked while parsing"),
+    }
+}
+
+impl Expression {
+    fn evaluate(&self, facts: &Facts) -> Result<bool, Error> {
+        match self {
+            Expression::Fact(fact) => match fact.as_str() {
+                "has_display" => Ok(facts.has_display()),
+                _ => unreachable!("facts are checked while parsing"),
+            },
+            Expression::Compare { fact, value, equal } => {
+                Ok((get_string_fact(facts, fact)? == *value) == *equal)
+            }
+            Expression::Not(expression) => Ok(!expression.evaluate(facts)?),
+            Expression::And(left, right) => Ok(left.evaluate(facts)? && right.evaluate(facts)?),
+            Expression::Or(left, right) => Ok(left.evaluate(facts)? || right.evaluate(facts)?),
+        }
+    }
+}
+
+/// A `when` expression like `arch == 'amd64' && has_display`, checked when it is read and
+/// evaluated against the facts of the machine.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    source: String,
+    expression: Expression,
+}
+
+impl Condition {
+    pub fn evaluate(&self, facts: &Facts) -> Result<bool, Error> {
+        self.expression.evaluate(facts)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(source: &str) -> Result<Condition, String> {
+        let mut parser = Parser {
+            tokens: tokenize(source)?,
+            position: 0,
+        };
+
+        let expression = parser.or()?;
+
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected {token}"));
+        }
+
+        Ok(Condition {
+            source: source.to_string(),
+            expression,
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Serialize for Condition {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for Condition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Condition, D::Error> {
+        let source = String::deserialize(deserializer)?;
+
+        source
+            .parse()
+            .map_err(|reason| de::Error::custom(format!("invalid condition `{source}`: {reason}")))
+    }
+}
+
+impl JsonSchema for Condition {
+    fn schema_name() -> Cow<'static, str> {
+        "Condition".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "string",
+            "description": format!(
+                "An expression over the facts {} and {}, combined with `&&`, `||`, `!` and parentheses, e.g. `arch == 'amd64' && has_display`.",
+                STRING_FACTS.join(", "),
+                BOOLEAN_FACTS.join(", ")
+            ),
+        })
+    }
+}
+
+/// Matches `*` against any run of characters and `?` against one, like shell globs.
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    fn matches(pattern: &[char], text: &[char]) -> bool {
+        match pattern.split_first() {
+            None => text.is_empty(),
+            Some(('*', rest)) => (0..=text.len()).any(|skip| matches(rest, &text[skip..])),
+            Some(('?', rest)) => !text.is_empty() && matches(rest, &text[1..]),
+            Some((c, rest)) => text.first() == Some(c) && matches(rest, &text[1..]),
+        }
+    }
+
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    matches(&pattern, &text)
+}
+
+/// Why an entry limited by `when` and `hosts` does not apply to this machine, or `None`
+/// when it does.
+pub fn get_exclusion_reason(
+    when: Option<&Condition>,
+    hosts: &[String],
+    facts: &Facts,
+) -> Result<Option<String>, Error> {
+    if !hosts.is_empty() {
+        let hostname = facts.hostname()?;
+
+        if !hosts.iter().any(|pattern| glob_matches(pattern, hostname)) {
+            return Ok(Some(format!(
+                "host {hostname} does not match {}",
+                hosts.join(", ")
+            )));
+        }
+    }
+
+    match when {
+        Some(when) if !when.evaluate(facts)? => Ok(Some(format!("`{when}` is false here"))),
+        _ => Ok(None),
+    }
+}
+
+/// An entry left out because its conditions do not hold here, skipped with the reason, or
+/// because they could not be evaluated, failed with the error.
+#[derive(Debug)]
+pub struct Excluded {
+    pub category: Category,
+    pub name: String,
+    pub outcome: Outcome,
+}
+
+/// Drops the entries whose `when` or `hosts` rule this machine out, and says why.
+pub fn retain_applicable(
+    facts: &Facts,
+    updates: &mut Option<Updates>,
+    tools: &mut Option<BTreeMap<String, ToolTarget>>,
+    keys: &mut Option<Keys>,
+) -> Vec<Excluded> {
+    let mut excluded = Vec::new();
+
+    let mut exclude = |category: Category, name: &str, reason: &Result<Option<String>, Error>| {
+        let outcome = match reason {
+            Ok(None) => return false,
+            Ok(Some(reason)) => Outcome::Skipped(reason.clone()),
+            Err(error) => Outcome::Failed(error.to_string()),
+        };
+
+        excluded.push(Excluded {
+            category,
+            name: name.to_string(),
+            outcome,
+        });
+
+        true
+    };
+
+    if let Some(section) = updates {
+        let reason = get_exclusion_reason(section.when.as_ref(), &section.hosts, facts);
+        let mut is_excluded = false;
+
+        for (name, enabled) in [
+            ("system", section.system),
+            ("dependencies", section.dependencies),
+            ("cleanup", section.cleanup),
+        ] {
+            if enabled.is_some() {
+                is_excluded |= exclude(Category::Update, name, &reason);
+            }
+        }
+
+        if is_excluded {
+            *updates = None;
+        }
+    }
+
+    if let Some(tools) = tools {
+        tools.retain(|name, tool_target| match tool_target {
+            ToolTarget::Keyword(_) => true,
+            ToolTarget::Settings(settings) => {
+                let reason = get_exclusion_reason(settings.when.as_ref(), &settings.hosts, facts);
+
+                !exclude(Category::Tool, name, &reason)
+            }
+        });
+    }
+
+    if let Some(keys) = keys {
+        if let Some(ssh) = &keys.ssh {
+            let reason = get_exclusion_reason(ssh.when.as_ref(), &ssh.hosts, facts);
+
+            if exclude(Category::Key, "ssh", &reason) {
+                keys.ssh = None;
+            }
+        }
+    }
+
+    excluded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ToolSettings;
+    use crate::facts::DEBIAN;
+
+    fn evaluate(source: &str) -> bool {
+        let condition = source.parse::<Condition>().unwrap();
+
+        condition.evaluate(&Facts::fixture(DEBIAN)).unwrap()
+    }
+
+    #[test]
+    fn operators_bind_like_in_c() {
+        // facts of x86_64 debian bookworm on the host `vm`
+        let cases = [
+            ("id == 'debian'", true),
+            ("id != 'debian'", false),
+            // && binds tighter than ||
+            ("id == 'debian' || id == 'fedora' && arch == 'arm64'", true),
+            (
+                "(id == 'debian' || id == 'fedora') && arch == 'arm64'",
+                false,
+            ),
+            (
+                "id == 'fedora' && arch == 'arm64' || hostname == 'vm'",
+                true,
+            ),
+            // ! applies to the comparison or parentheses that follow it
+            ("!id == 'fedora'", true),
+            ("!(id == 'debian' && arch == 'amd64')", false),
+            ("!!release == 'bookworm'", true),
+            (
+                "machine == 'x86_64' && package_manager == 'apt' && kernel == 'Linux'",
+                true,
+            ),
+            ("id == \"debian\"", true),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(evaluate(source), expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn and_groups_before_or() {
+        let condition = "has_display || id == 'debian' && !has_display"
+            .parse::<Condition>()
+            .unwrap();
+        let fact = || Box::new(Expression::Fact("has_display".to_string()));
+        let debian = Box::new(Expression::Compare {
+            fact: "id".to_string(),
+            value: "debian".to_string(),
+            equal: true,
+        });
+
+        assert_eq!(
+            condition.expression,
+            Expression::Or(
+                fact(),
+                Box::new(Expression::And(debian, Box::new(Expression::Not(fact()))))
+            )
+        );
+    }
+
+    #[test]
+    fn malformed_conditions_are_rejected() {
+        let cases = [
+            ("", "unexpected end"),
+            ("id == 'debian' &&", "unexpected end"),
+            ("id == 'debian')", "unexpected `)`"),
+            ("id == 'debian' arch == 'amd64'", "unexpected `arch`"),
+            ("(id == 'debian'", "expected `)`"),
+            ("(id == 'debian' 'x'", "expected `)`, found 'x'"),
+            ("&& id == 'debian'", "expected a fact, found `&&`"),
+            ("id == debian", "expected a quoted string, found `debian`"),
+            ("id ==", "expected a quoted string"),
+            ("id = 'debian'", "unexpected `=`"),
+            ("id == 'debian", "unterminated string 'debian"),
+            ("arch", "expected `==` or `!=` after `arch`"),
+            (
+                "has_display == 'yes'",
+                "`has_display` is true or false by itself, it cannot be compared",
+            ),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(
+                source.parse::<Condition>().unwrap_err(),
+                expected,
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_facts_are_reported_with_a_suggestion() {
+        let cases = [
+            (
+                "hostnme == 'vm'",
+                "unknown fact `hostnme`, did you mean `hostname`?",
+            ),
+            ("has_dispaly", "unknown fact `has_dispaly`, did you mean `has_display`?"),
+            (
+                "colour == 'blue'",
+                "expected one of arch, hostname, id, kernel, machine, package_manager, release, found `colour`",
+            ),
+            ("wayland", "expected one of has_display, found `wayland`"),
+        ];
+
+        for (source, expected) in cases {
+            assert_eq!(
+                source.parse::<Condition>().unwrap_err(),
+                expected,
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn globs_match_like_the_shell() {
+        let cases = [
+            ("*", "", true),
+            ("*", "dev-laptop", true),
+            ("dev-*", "dev-laptop", true),
+            ("dev-*", "dev-", true),
+            ("dev-*", "dev", false),
+            ("*-laptop", "dev-laptop", true),
+            ("*-laptop", "dev-laptop-2", false),
+            ("*dev*", "my-dev-box", true),
+            ("*dev*", "my-box", false),
+            ("dev-?", "dev-1", true),
+            ("dev-?", "dev-12", false),
+            ("", "", true),
+            ("", "vm", false),
+            ("vm", "vm", true),
+            ("vm", "vm1", false),
+            ("vm", "VM", false),
+        ];
+
+        for (pattern, text, expected) in cases {
+            assert_eq!(glob_matches(pattern, text), expected, "{pattern} ~ {text}");
+        }
+    }
+
+    #[test]
+    fn exclusion_reasons_name_what_did_not_match() {
+        let facts = Facts::fixture(DEBIAN);
+        let fedora = "id == 'fedora'".parse::<Condition>().unwrap();
+        let debian = "id == 'debian'".parse::<Condition>().unwrap();
+        let hosts = ["dev-*".to_string(), "work-?".to_string()];
+        let vm = ["v*".to_string()];
+
+        let cases = [
+            (None, &[][..], None),
+            (Some(&debian), &vm[..], None),
+            (
+                Some(&fedora),
+                &[][..],
+                Some("`id == 'fedora'` is false here"),
+            ),
+            (
+                None,
+                &hosts[..],
+                Some("host vm does not match dev-*, work-?"),
+            ),
+            // the hosts are checked first
+            (
+                Some(&fedora),
+                &hosts[..],
+                Some("host vm does not match dev-*, work-?"),
+            ),
+        ];
+
+        for (when, hosts, expected) in cases {
+            let reason = get_exclusion_reason(when, hosts, &facts).unwrap();
+
+            assert_eq!(reason.as_deref(), expected, "{when:?} {hosts:?}");
+        }
+    }
+
+    #[test]
+    fn inapplicable_entries_are_skipped_with_their_reason() {
+        let facts = Facts::fixture(DEBIAN);
+        let fedora_only = ToolSettings {
+            when: Some("id == 'fedora'".parse().unwrap()),
+            ..ToolSettings::default()
+        };
+        let mut updates = Some(Updates {
+            system: Some(true),
+            hosts: vec!["dev-*".to_string()],
+            ..Updates::default()
+        });
+        let mut tools = Some(BTreeMap::from([
+            ("git".to_string(), ToolTarget::Keyword("latest".to_string())),
+            ("node".to_string(), ToolTarget::Settings(fedora_only)),
+        ]));
+
+        let excluded = retain_applicable(&facts, &mut updates, &mut tools, &mut None);
+        let reasons = excluded
+            .iter()
+            .map(|excluded| (excluded.category, excluded.name.as_str(), &excluded.outcome))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            reasons,
+            [
+                (
+                    Category::Update,
+                    "system",
+                    &Outcome::Skipped("host vm does not match dev-*".to_string())
+                ),
+                (
+                    Category::Tool,
+                    "node",
+                    &Outcome::Skipped("`id == 'fedora'` is false here".to_string())
+                ),
+            ]
+        );
+        assert!(updates.is_none());
+        assert_eq!(tools.unwrap().keys().collect::<Vec<_>>(), ["git"]);
+    }
+}
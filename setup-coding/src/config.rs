@@ -0,0 +1,1175 @@
+use crate::condition::{glob_matches, Condition};
+use crate::error::Error;
+use crate::facts::Facts;
+use crate::interpolation::{Interpolating, Variables};
+use crate::keys::SSH_ALGORITHMS;
+use crate::progress;
+use crate::recipe::Recipe;
+use crate::registry::Registry;
+use regex::Regex;
+use schemars::JsonSchema;
+use semver::VersionReq;
+use serde::de::{self, IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+use serde_derive::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use toml::Spanned;
+
+#[derive(Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TargetEnvironment {
+    /// Configs this one builds on, relative to this file. Later ones override earlier ones
+    /// and this file overrides them all.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include: Vec<PathBuf>,
+    /// Recipes for tools that are not built in, in the format of the built-in `recipes.toml`.
+    pub custom_tools: Option<Vec<Recipe>>,
+    pub keys: Option<Keys>,
+    /// The tools to install or remove, by name.
+    pub tools: Option<BTreeMap<String, ToolTarget>>,
+    pub updates: Option<Updates>,
+    /// Overlays for machines by hostname glob like `dev-*`, applied on top of the rest of
+    /// this file.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hosts: BTreeMap<String, TargetEnvironment>,
+}
+
+impl TargetEnvironment {
+    /// Lays `overlay` over this config.
+    ///
+    /// Tools, custom tools and updates are merged by name, so the overlay overrides the
+    /// entries it names and keeps the others; the ssh key is replaced as a whole. `include`
+    /// and `hosts` are left alone, they are resolved while reading.
+    pub fn merge(&mut self, overlay: TargetEnvironment) {
+        if let Some(custom_tools) = overlay.custom_tools {
+            let base = self.custom_tools.get_or_insert_with(Vec::new);
+
+            for recipe in custom_tools {
+                base.retain(|existing| existing.name != recipe.name);
+                base.push(recipe);
+            }
+        }
+
+        if let Some(ssh) = overlay.keys.and_then(|keys| keys.ssh) {
+            self.keys.get_or_insert(Keys { ssh: None }).ssh = Some(ssh);
+        }
+
+        if let Some(tools) = overlay.tools {
+            self.tools.get_or_insert_with(BTreeMap::new).extend(tools);
+        }
+
+        if let Some(updates) = overlay.updates {
+            let base = self.updates.get_or_insert_with(Updates::default);
+
+            base.cleanup = updates.cleanup.or(base.cleanup);
+            base.dependencies = updates.dependencies.or(base.dependencies);
+            base.system = updates.system.or(base.system);
+            base.when = updates.when.or(base.when.take());
+
+            if !updates.hosts.is_empty() {
+                base.hosts = updates.hosts;
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Keys {
+    /// An ssh key generated with `ssh-keygen` and added to the agent and to github.
+    pub ssh: Option<Ssh>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Ssh {
+    #[serde(deserialize_with = "deserialize_ssh_algorithm")]
+    #[schemars(extend("enum" = SSH_ALGORITHMS))]
+    pub algorithm: String,
+    /// An email address, checked after variables like `${env:GIT_EMAIL}` are resolved.
+    pub email: String,
+    /// The name of the key on github.
+    pub title: String,
+    /// Only act on this entry when the expression holds for this machine.
+    pub when: Option<Condition>,
+    /// Only act on this entry on hosts whose name matches one of these globs, like `dev-*`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+}
+
+/// A tool is either `"latest"`, `"absent"` or a table of settings.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ToolTarget {
+    Keyword(#[schemars(extend("enum" = TOOL_KEYWORDS))] String),
+    Settings(ToolSettings),
+}
+
+/// `install` picks one of the recipe's install methods by name, `sha256` is checked against
+/// the binary downloaded for `version`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ToolSettings {
+    #[schemars(with = "Option<String>")]
+    pub version: Option<VersionReq>,
+    pub install: Option<String>,
+    pub sha256: Option<String>,
+    #[serde(default, skip_serializing_if = "State::is_present")]
+    pub state: State,
+    /// Only act on this entry when the expression holds for this machine.
+    pub when: Option<Condition>,
+    /// Only act on this entry on hosts whose name matches one of these globs, like `dev-*`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+}
+
+/// Whether a tool should be installed or removed.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum State {
+    #[default]
+    Present,
+    Absent,
+}
+
+impl State {
+    fn is_present(&self) -> bool {
+        *self == State::Present
+    }
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Updates {
+    /// Remove packages nothing depends on anymore.
+    pub cleanup: Option<bool>,
+    /// Install the libraries and tools most builds need.
+    pub dependencies: Option<bool>,
+    /// Refresh the package index.
+    pub system: Option<bool>,
+    /// Only act on these updates when the expression holds for this machine.
+    pub when: Option<Condition>,
+    /// Only act on these updates on hosts whose name matches one of these globs, like `dev-*`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub hosts: Vec<String>,
+}
+
+impl Updates {
+    /// Whether the config turns the update called `name` on or off, if it says.
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "system" => self.system,
+            "dependencies" => self.dependencies,
+            "cleanup" => self.cleanup,
+            _ => None,
+        }
+    }
+}
+
+const TOOL_KEYWORDS: &[&str] = &["latest", "absent"];
+
+// a table is handed to `ToolSettings` directly instead of going through `untagged`, so that
+// its errors say which field is wrong rather than that no variant matched
+impl<'de> Deserialize<'de> for ToolTarget {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<ToolTarget, D::Error> {
+        struct ToolTargetVisitor;
+
+        impl<'de> Visitor<'de> for ToolTargetVisitor {
+            type Value = ToolTarget;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "\"latest\", \"absent\" or a table with a `version`")
+            }
+
+            fn visit_str<E: de::Error>(self, keyword: &str) -> Result<ToolTarget, E> {
+                if TOOL_KEYWORDS.contains(&keyword) {
+                    return Ok(ToolTarget::Keyword(keyword.to_string()));
+                }
+
+                Err(E::custom(match suggest(keyword, TOOL_KEYWORDS.iter().copied()) {
+                    Some(candidate) => {
+                        format!("\"{keyword}\" is not supported, did you mean \"{candidate}\"?")
+                    }
+                    None => format!(
+                        "\"{keyword}\" is not supported, use \"latest\", \"absent\" or a table with a `version`"
+                    ),
+                }))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<ToolTarget, A::Error> {
+                ToolSettings::deserialize(de::value::MapAccessDeserializer::new(map))
+                    .map(ToolTarget::Settings)
+            }
+        }
+
+        deserializer.deserialize_any(ToolTargetVisitor)
+    }
+}
+
+fn deserialize_ssh_algorithm<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<String, D::Error> {
+    let algorithm = String::deserialize(deserializer)?;
+
+    if !SSH_ALGORITHMS.contains(&algorithm.as_str()) {
+        return Err(de::Error::custom(
+            match suggest(&algorithm, SSH_ALGORITHMS.iter().copied()) {
+                Some(candidate) => {
+                    format!("unsupported ssh algorithm `{algorithm}`, did you mean `{candidate}`?")
+                }
+                None => format!(
+                    "unsupported ssh algorithm `{algorithm}`, use one of {}",
+                    SSH_ALGORITHMS.join(", ")
+                ),
+            },
+        ));
+    }
+
+    Ok(algorithm)
+}
+
+/// Whether `text` looks like an email address, with a dot in its domain.
+///
+/// `user@host`, the comment ssh-keygen gives keys by default, does not.
+pub fn is_email(text: &str) -> bool {
+    let pattern = Regex::new(r"^[^@\s]+@[^@\s]+\.[^@\s.]+$").expect("the email pattern is valid");
+
+    pattern.is_match(text)
+}
+
+/// The candidate closest to a misspelled `name`, if any is close enough to be a typo.
+pub fn suggest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .into_iter()
+        .map(|candidate| (strsim::jaro_winkler(name, candidate), candidate))
+        .filter(|(similarity, _)| *similarity > 0.8)
+        .max_by(|(left, _), (right, _)| left.total_cmp(right))
+        .map(|(_, candidate)| candidate)
+}
+
+/// `", did you mean `tools`?"` for a close candidate, or nothing.
+pub fn did_you_mean<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> String {
+    suggest(name, candidates)
+        .map(|candidate| format!(", did you mean `{candidate}`?"))
+        .unwrap_or_default()
+}
+
+/// The 1-based line and column of a byte offset, the way editors count them.
+fn line_column(contents: &str, offset: usize) -> (usize, usize) {
+    let before = &contents[..offset.min(contents.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .map_or(0, |line| line.chars().count())
+        + 1;
+
+    (line, column)
+}
+
+/// Turns a parser or serde error message into one that points at the offending key,
+/// suggesting the closest valid name when the key or variant is unknown.
+fn parse_error(path: &Path, message: &str, position: Option<(usize, usize)>) -> Error {
+    let message = message.trim_end();
+    let unknown = Regex::new(r"unknown (field|variant) `([^`]*)`, (?:expected|there are no) (.*)$")
+        .expect("the unknown key pattern is valid");
+
+    let reason = match unknown.captures(message) {
+        None => message.to_string(),
+        Some(captures) => {
+            let name = &captures[2];
+            let expected = Regex::new(r"`([^`]*)`").expect("the expected key pattern is valid");
+            let candidates = expected
+                .captures_iter(&captures[3])
+                .map(|candidate| candidate.get(1).map_or("", |candidate| candidate.as_str()))
+                .collect::<Vec<_>>();
+
+            match suggest(name, candidates) {
+                None => message.to_string(),
+                Some(candidate) => format!(
+                    "unknown {} `{name}`, did you mean `{candidate}`?",
+                    &captures[1]
+                ),
+            }
+        }
+    };
+
+    Error::ParseConfig {
+        path: path.to_path_buf(),
+        position,
+        reason,
+    }
+}
+
+/// The file formats a config can be written in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl Format {
+    /// The format of a file by its extension: `.yaml` or `.yml`, `.json`, and TOML otherwise.
+    pub fn from_path(path: &Path) -> Format {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+
+        match extension.as_deref() {
+            Some("yaml" | "yml") => Format::Yaml,
+            Some("json") => Format::Json,
+            _ => Format::Toml,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Format, String> {
+        match name {
+            "toml" => Ok(Format::Toml),
+            "yaml" | "yml" => Ok(Format::Yaml),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown format `{name}`, use toml, yaml or json")),
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Format::Toml => write!(f, "toml"),
+            Format::Yaml => write!(f, "yaml"),
+            Format::Json => write!(f, "json"),
+        }
+    }
+}
+
+fn deserialize<'de, T: Deserialize<'de>, D: Deserializer<'de>>(
+    deserializer: D,
+    variables: Option<Variables>,
+) -> Result<T, D::Error> {
+    match variables {
+        None => T::deserialize(deserializer),
+        Some(variables) => T::deserialize(Interpolating::new(deserializer, variables)),
+    }
+}
+
+/// Deserializes the contents of a config file, resolving variables when `variables` are given.
+///
+/// Errors point at their line and column in every format.
+fn parse_config<'de, T: Deserialize<'de>>(
+    path: &Path,
+    contents: &'de str,
+    format: Format,
+    variables: Option<Variables>,
+) -> Result<T, Error> {
+    // the YAML and JSON messages include the position, which is reported separately; YAML
+    // syntax errors go on to say where what was being parsed started, which is kept
+    let strip_position = |message: String| {
+        let position =
+            Regex::new(r",? at line \d+ column \d+").expect("the position pattern is valid");
+
+        position.replace(&message, "").into_owned()
+    };
+
+    match format {
+        Format::Toml => deserialize(toml::Deserializer::new(contents), variables).map_err(
+            |source: toml::de::Error| {
+                let position = source.span().map(|span| line_column(contents, span.start));
+
+                parse_error(path, source.message(), position)
+            },
+        ),
+        Format::Yaml => deserialize(serde_yaml_ng::Deserializer::from_str(contents), variables)
+            .map_err(|source: serde_yaml_ng::Error| {
+                let position = source
+                    .location()
+                    .map(|location| (location.line(), location.column()));
+
+                parse_error(path, &strip_position(source.to_string()), position)
+            }),
+        Format::Json => {
+            let mut deserializer = serde_json::Deserializer::from_str(contents);
+
+            deserialize(&mut deserializer, variables)
+                .and_then(|value| deserializer.end().map(|()| value))
+                .map_err(|source| {
+                    let position = Some((source.line(), source.column()));
+
+                    parse_error(path, &strip_position(source.to_string()), position)
+                })
+        }
+    }
+}
+
+/// Only the tool names, with where they are in TOML, to point at a tool that does not exist.
+#[derive(Deserialize)]
+#[serde(bound = "K: Deserialize<'de> + Ord")]
+struct ToolNames<K: Ord> {
+    #[serde(default)]
+    tools: BTreeMap<K, IgnoredAny>,
+    #[serde(default)]
+    hosts: BTreeMap<String, ToolNames<K>>,
+}
+
+impl<K: Ord> ToolNames<K> {
+    fn all(&self) -> Vec<&K> {
+        let mut names = self.tools.keys().collect::<Vec<_>>();
+
+        for overlay in self.hosts.values() {
+            names.extend(overlay.all());
+        }
+
+        names
+    }
+}
+
+/// Checks that every tool of one file is either built in or a custom tool of the merged
+/// config, which may come from another file.
+///
+/// Only TOML errors say where the tool is; the YAML and JSON deserializers do not give the
+/// positions of map keys, so theirs only name the file.
+fn validate_tool_names(layer: &Layer, known: &[&str]) -> Result<(), Error> {
+    let names = match layer.format {
+        Format::Toml => {
+            let tool_names: ToolNames<Spanned<String>> =
+                parse_config(&layer.path, &layer.contents, layer.format, None)?;
+
+            tool_names
+                .all()
+                .into_iter()
+                .map(|name| {
+                    let position = line_column(&layer.contents, name.span().start);
+
+                    (name.get_ref().clone(), Some(position))
+                })
+                .collect::<Vec<_>>()
+        }
+        format => {
+            let tool_names: ToolNames<String> =
+                parse_config(&layer.path, &layer.contents, format, None)?;
+
+            tool_names
+                .all()
+                .into_iter()
+                .map(|name| (name.clone(), None))
+                .collect()
+        }
+    };
+
+    for (name, position) in names {
+        if known.contains(&name.as_str()) {
+            continue;
+        }
+
+        return Err(Error::ParseConfig {
+            path: layer.path.clone(),
+            position,
+            reason: format!(
+                "unknown tool `{name}`{}",
+                did_you_mean(&name, known.iter().copied())
+            ),
+        });
+    }
+
+    Ok(())
+}
+
+/// Only where the ssh key settings are in TOML, to point at an email that is not one.
+#[derive(Deserialize)]
+struct KeySpans {
+    #[serde(default)]
+    keys: BTreeMap<String, BTreeMap<String, Spanned<IgnoredAny>>>,
+    #[serde(default)]
+    hosts: BTreeMap<String, KeySpans>,
+}
+
+impl KeySpans {
+    fn email(&self) -> Option<&Spanned<IgnoredAny>> {
+        self.keys.get("ssh").and_then(|ssh| ssh.get("email"))
+    }
+}
+
+/// Checks that the ssh key emails of one file, and of its overlays, look like email
+/// addresses once their variables are resolved.
+///
+/// This is not part of deserializing, so that a file can be read as it is written, with
+/// an email like `${env:GIT_EMAIL}`.
+fn validate_emails(layer: &Layer) -> Result<(), Error> {
+    let email = |target_environment: &TargetEnvironment| {
+        target_environment
+            .keys
+            .as_ref()
+            .and_then(|keys| keys.ssh.as_ref())
+            .map(|ssh| ssh.email.clone())
+    };
+
+    let emails = std::iter::once((None, email(&layer.target_environment))).chain(
+        layer
+            .target_environment
+            .hosts
+            .iter()
+            .map(|(pattern, overlay)| (Some(pattern), email(overlay))),
+    );
+
+    for (pattern, email) in emails {
+        let email = match email {
+            Some(email) if !is_email(&email) => email,
+            _ => continue,
+        };
+
+        // only the TOML parser knows where values are
+        let position = match layer.format {
+            Format::Toml => {
+                let key_spans: KeySpans =
+                    parse_config(&layer.path, &layer.contents, layer.format, None)?;
+                let key_spans = match pattern {
+                    None => Some(&key_spans),
+                    Some(pattern) => key_spans.hosts.get(pattern),
+                };
+
+                key_spans
+                    .and_then(KeySpans::email)
+                    .map(|email| line_column(&layer.contents, email.span().start))
+            }
+            _ => None,
+        };
+
+        return Err(Error::ParseConfig {
+            path: layer.path.clone(),
+            position,
+            reason: format!("`{email}` does not look like an email address"),
+        });
+    }
+
+    Ok(())
+}
+
+/// One file of a config, kept with its contents to point into it.
+struct Layer {
+    path: PathBuf,
+    format: Format,
+    contents: String,
+    target_environment: TargetEnvironment,
+}
+
+fn read_layer(path: &Path, format: Format, variables: Variables) -> Result<Layer, Error> {
+    progress!("\nreading file: {}", path.display());
+
+    let contents = fs::read_to_string(path).map_err(|source| Error::ReadConfig {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    let target_environment = parse_config(path, &contents, format, Some(variables))?;
+    let layer = Layer {
+        path: path.to_path_buf(),
+        format,
+        contents,
+        target_environment,
+    };
+
+    validate_emails(&layer)?;
+
+    Ok(layer)
+}
+
+/// Reads `path` after everything it includes, so that the layers are in merge order.
+///
+/// `including` holds the files on the way to `path`, to reject a file including itself.
+/// Included files are read in the format of their extension.
+fn read_layers(
+    path: &Path,
+    format: Format,
+    variables: Variables,
+    including: &mut Vec<PathBuf>,
+    layers: &mut Vec<Layer>,
+) -> Result<(), Error> {
+    let canonical_path = fs::canonicalize(path).map_err(|source| Error::ReadConfig {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    if including.contains(&canonical_path) {
+        let cycle = including
+            .iter()
+            .chain([&canonical_path])
+            .map(|path| path.display().to_string())
+            .collect::<Vec<_>>();
+
+        return Err(Error::InvalidConfig(format!(
+            "the includes form a cycle: {}",
+            cycle.join(" -> ")
+        )));
+    }
+
+    let mut layer = read_layer(path, format, variables)?;
+
+    for overlay in layer.target_environment.hosts.values() {
+        if !overlay.include.is_empty() || !overlay.hosts.is_empty() {
+            return Err(Error::InvalidConfig(format!(
+                "{}: `include` and `hosts` are not supported inside `hosts`",
+                path.display()
+            )));
+        }
+    }
+
+    let directory = path.parent().unwrap_or(Path::new(""));
+
+    including.push(canonical_path);
+
+    for include in std::mem::take(&mut layer.target_environment.include) {
+        let include = directory.join(include);
+
+        read_layers(
+            &include,
+            Format::from_path(&include),
+            variables,
+            including,
+            layers,
+        )?;
+    }
+
+    including.pop();
+    layers.push(layer);
+
+    Ok(())
+}
+
+/// Where a config is looked for when none is given: `./setup-coding.toml`, then
+/// `$XDG_CONFIG_HOME/setup-coding/config.toml` (`~/.config` when it is not set).
+pub fn get_default_config_paths() -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from("./setup-coding.toml")];
+
+    let config_home = match env::var("XDG_CONFIG_HOME") {
+        Ok(config_home) if !config_home.is_empty() => Some(PathBuf::from(config_home)),
+        _ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+    };
+
+    if let Some(config_home) = config_home {
+        paths.push(config_home.join("setup-coding").join("config.toml"));
+    }
+
+    paths
+}
+
+/// The first default config path that exists.
+pub fn find_config_path() -> Result<PathBuf, Error> {
+    let searched = get_default_config_paths();
+
+    match searched.iter().find(|path| path.is_file()) {
+        None => Err(Error::MissingConfig { searched }),
+        Some(path) => Ok(path.clone()),
+    }
+}
+
+/// Reads a config with everything it includes, merges it into one and validates it before
+/// anything acts on it.
+///
+/// Each file is merged after the files it includes, followed by its `hosts` overlays that
+/// match the hostname. `${env:NAME}` and `${fact:NAME}` in string values are resolved
+/// against the environment and `facts` as each file is read.
+pub fn read_target_environment(
+    path: &Path,
+    format: Format,
+    facts: &Facts,
+) -> Result<TargetEnvironment, Error> {
+    let mut layers = Vec::new();
+    let variables = Variables::new(facts);
+
+    read_layers(path, format, variables, &mut Vec::new(), &mut layers)?;
+
+    let mut merged = TargetEnvironment::default();
+
+    for layer in &mut layers {
+        let target_environment = std::mem::take(&mut layer.target_environment);
+        let hosts = target_environment.hosts;
+
+        merged.merge(TargetEnvironment {
+            hosts: BTreeMap::new(),
+            ..target_environment
+        });
+
+        let hostname = match facts.hostname() {
+            Err(_) => continue,
+            Ok(hostname) => hostname,
+        };
+
+        for (pattern, overlay) in hosts {
+            if glob_matches(&pattern, hostname) {
+                progress!("applying overlay for hosts: {pattern}");
+
+                merged.merge(overlay);
+            }
+        }
+    }
+
+    let registry = Registry::builtin()?;
+    let custom_tools = merged.custom_tools.as_deref().unwrap_or_default();
+    let known = registry
+        .recipes()
+        .iter()
+        .chain(custom_tools)
+        .map(|recipe| recipe.name.as_str())
+        .collect::<Vec<_>>();
+
+    for layer in &layers {
+        validate_tool_names(layer, &known)?;
+    }
+
+    Ok(merged)
+}
+
+/// Reads one config file as it is written, without its includes, overlays or variables
+/// resolved, e.g. to convert it to another format.
+pub fn read_config_file(path: &Path, format: Format) -> Result<TargetEnvironment, Error> {
+    let contents = fs::read_to_string(path).map_err(|source| Error::ReadConfig {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    parse_config(path, &contents, format, None)
+}
+
+/// Drops the `null`s of unset options, which TOML leaves out and which mean the same.
+fn remove_nulls(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|_, value| !value.is_null());
+            map.values_mut().for_each(remove_nulls);
+        }
+        Value::Array(values) => values.iter_mut().for_each(remove_nulls),
+        _ => {}
+    }
+}
+
+/// Renders a target environment in `format`, with the keys in the same order in all of them.
+///
+/// TOML puts plain values ahead of tables, as it requires.
+pub fn format_config(
+    target_environment: &TargetEnvironment,
+    format: Format,
+) -> Result<String, Error> {
+    if format == Format::Toml {
+        let value = toml::Value::try_from(target_environment)
+            .map_err(|source| Error::SerializeConfig(Box::new(source)))?;
+
+        return toml::to_string(&value).map_err(|source| Error::SerializeConfig(Box::new(source)));
+    }
+
+    let mut value = serde_json::to_value(target_environment)
+        .map_err(|source| Error::SerializeConfig(Box::new(source)))?;
+
+    remove_nulls(&mut value);
+
+    match format {
+        Format::Yaml => serde_yaml_ng::to_string(&value)
+            .map_err(|source| Error::SerializeConfig(Box::new(source))),
+        _ => serde_json::to_string_pretty(&value)
+            .map(|json| json + "\n")
+            .map_err(|source| Error::SerializeConfig(Box::new(source))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::facts::DEBIAN;
+
+    const SSH_KEY: &str = r#"
+[keys.ssh]
+algorithm = "ed25519"
+email = "${env:SETUP_CODING_TEST_EMAIL:-root@vm}"
+title = "laptop"
+"#;
+
+    /// Writes `contents` to a config file of its own and reads it back with `read`.
+    fn with_config<T>(name: &str, contents: &str, read: impl FnOnce(&Path) -> T) -> T {
+        with_configs(name, &[("setup-coding.toml", contents)], read)
+    }
+
+    /// Writes `files` by their paths into a directory of their own and reads the first back
+    /// with `read`.
+    fn with_configs<T>(name: &str, files: &[(&str, &str)], read: impl FnOnce(&Path) -> T) -> T {
+        let directory = env::temp_dir().join(format!("setup-coding-{name}-{}", std::process::id()));
+
+        for (file, contents) in files {
+            let path = directory.join(file);
+
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, contents).unwrap();
+        }
+
+        let result = read(&directory.join(files[0].0));
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        result
+    }
+
+    /// The tools of `target_environment` as they would be written in JSON.
+    fn tools_of(target_environment: &TargetEnvironment) -> Value {
+        let mut tools = serde_json::to_value(&target_environment.tools).unwrap();
+
+        remove_nulls(&mut tools);
+
+        tools
+    }
+
+    #[test]
+    fn merge_overrides_named_entries_and_keeps_the_others() {
+        let mut merged = toml::from_str::<TargetEnvironment>(
+            r#"
+[tools]
+git = "latest"
+node = { version = "18" }
+
+[[custom_tools]]
+name = "jq"
+detect = [{ command = ["jq", "--version"] }]
+install = [{ type = "script", url = "https://example.com/jq" }]
+
+[[custom_tools]]
+name = "deno"
+detect = [{ command = ["deno", "--version"] }]
+install = [{ type = "script", url = "https://example.com/deno" }]
+
+[keys.ssh]
+algorithm = "ed25519"
+email = "base@example.com"
+title = "laptop"
+hosts = ["dev-*"]
+
+[updates]
+system = true
+dependencies = true
+hosts = ["dev-*"]
+"#,
+        )
+        .unwrap();
+        let overlay = toml::from_str::<TargetEnvironment>(
+            r#"
+[tools]
+node = "absent"
+gh = "latest"
+
+[[custom_tools]]
+name = "jq"
+detect = [{ command = ["jq", "-V"] }]
+install = [{ type = "script", url = "https://example.com/jq" }]
+
+[[custom_tools]]
+name = "terraform"
+detect = [{ command = ["terraform", "--version"] }]
+install = [{ type = "script", url = "https://example.com/terraform" }]
+
+[keys.ssh]
+algorithm = "rsa"
+email = "overlay@example.com"
+title = "desktop"
+
+[updates]
+dependencies = false
+cleanup = true
+"#,
+        )
+        .unwrap();
+
+        merged.merge(overlay);
+
+        assert_eq!(
+            tools_of(&merged),
+            serde_json::json!({ "gh": "latest", "git": "latest", "node": "absent" })
+        );
+
+        let custom_tools = merged.custom_tools.unwrap();
+        let names = custom_tools
+            .iter()
+            .map(|recipe| recipe.name.as_str())
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, ["deno", "jq", "terraform"]);
+        assert_eq!(custom_tools[1].detect[0].command, ["jq", "-V"]);
+
+        // the key is replaced as a whole, so the base's hosts do not carry over
+        let ssh = merged.keys.unwrap().ssh.unwrap();
+
+        assert_eq!(
+            (
+                ssh.algorithm.as_str(),
+                ssh.email.as_str(),
+                ssh.title.as_str()
+            ),
+            ("rsa", "overlay@example.com", "desktop")
+        );
+        assert!(ssh.hosts.is_empty());
+
+        // updates are merged field by field, and an overlay without hosts keeps the base's
+        let updates = merged.updates.unwrap();
+
+        assert_eq!(
+            (updates.system, updates.dependencies, updates.cleanup),
+            (Some(true), Some(false), Some(true))
+        );
+        assert_eq!(updates.hosts, ["dev-*"]);
+    }
+
+    #[test]
+    fn merge_keeps_everything_an_empty_overlay_leaves_out() {
+        let mut merged = toml::from_str::<TargetEnvironment>(
+            r#"
+[tools]
+git = "latest"
+
+[updates]
+system = true
+"#,
+        )
+        .unwrap();
+
+        merged.merge(TargetEnvironment::default());
+
+        assert_eq!(tools_of(&merged), serde_json::json!({ "git": "latest" }));
+        assert_eq!(merged.updates.unwrap().system, Some(true));
+        assert!(merged.keys.is_none() && merged.custom_tools.is_none());
+    }
+
+    #[test]
+    fn includes_are_resolved_relative_to_the_including_file() {
+        let facts = Facts::fixture(DEBIAN);
+        let files = [
+            (
+                "setup-coding.toml",
+                "include = [\"layers/team.toml\"]\n\n[tools]\nnode = \"latest\"\n",
+            ),
+            (
+                "layers/team.toml",
+                "include = [\"base.yaml\"]\n\n[tools]\ngh = \"latest\"\nnode = \"absent\"\n",
+            ),
+            ("layers/base.yaml", "tools:\n  git: latest\n  gh: absent\n"),
+            // a file of the same name next to the top config, which must not be read
+            ("base.yaml", "tools:\n  rustc: latest\n"),
+        ];
+
+        let target_environment = with_configs("includes", &files, |path| {
+            read_target_environment(path, Format::Toml, &facts)
+        })
+        .unwrap();
+
+        // each file overrides the ones it includes
+        assert_eq!(
+            tools_of(&target_environment),
+            serde_json::json!({ "gh": "latest", "git": "latest", "node": "latest" })
+        );
+    }
+
+    #[test]
+    fn includes_that_lead_back_to_a_file_are_a_cycle() {
+        let facts = Facts::fixture(DEBIAN);
+        let files = [
+            ("setup-coding.toml", "include = [\"shared/base.toml\"]\n"),
+            // the same file by another path
+            (
+                "shared/base.toml",
+                "include = [\"../shared/../setup-coding.toml\"]\n",
+            ),
+        ];
+
+        let (error, directory) = with_configs("include-cycle", &files, |path| {
+            let error = read_target_environment(path, Format::Toml, &facts).unwrap_err();
+
+            (
+                error.to_string(),
+                fs::canonicalize(path.parent().unwrap()).unwrap(),
+            )
+        });
+
+        assert_eq!(
+            error,
+            format!(
+                "invalid config: the includes form a cycle: {0}/setup-coding.toml -> {0}/shared/base.toml -> {0}/setup-coding.toml",
+                directory.display()
+            )
+        );
+    }
+
+    #[test]
+    fn host_overlays_apply_on_matching_hosts_after_their_file() {
+        let facts = Facts::fixture(DEBIAN);
+        let files = [
+            (
+                "setup-coding.toml",
+                "include = [\"base.toml\"]\n\n[tools]\ngh = \"absent\"\n",
+            ),
+            (
+                "base.toml",
+                r#"
+[tools]
+git = "latest"
+gh = "latest"
+
+[hosts."v*".tools]
+gh = "latest"
+node = "latest"
+
+[hosts."dev-*".tools]
+git = "absent"
+"#,
+            ),
+        ];
+
+        let target_environment = with_configs("host-overlays", &files, |path| {
+            read_target_environment(path, Format::Toml, &facts)
+        })
+        .unwrap();
+
+        // `vm` matches `v*` but not `dev-*`, and the including file still overrides the
+        // overlay of the file it includes
+        assert_eq!(
+            tools_of(&target_environment),
+            serde_json::json!({ "gh": "absent", "git": "latest", "node": "latest" })
+        );
+        assert!(target_environment.hosts.is_empty());
+    }
+
+    #[test]
+    fn parse_errors_point_at_their_line_and_column_in_every_format() {
+        let cases = [
+            (
+                Format::Toml,
+                "[tools.node]\nversoin = \"18\"\n",
+                "2:1: unknown field `versoin`, did you mean `version`?",
+            ),
+            (
+                Format::Yaml,
+                "tools:\n  node:\n    versoin: \"18\"\n",
+                "3:5: unknown field `versoin`, did you mean `version`?",
+            ),
+            (
+                Format::Json,
+                "{\n  \"tools\": {\n    \"node\": { \"versoin\": \"18\" }\n  }\n}\n",
+                "3:23: unknown field `versoin`, did you mean `version`?",
+            ),
+            (
+                Format::Toml,
+                "[updates]\nsystem = yes\n",
+                "2:10: invalid string\nexpected `\"`, `'`",
+            ),
+            (
+                Format::Yaml,
+                "updates:\n  system: [true]\n",
+                "2:11: updates.system: invalid type: sequence, expected a boolean",
+            ),
+            (
+                Format::Yaml,
+                "tools: {git: latest\n",
+                "2:1: did not find expected ',' or '}', while parsing a flow mapping at line 1 column 8",
+            ),
+            (
+                Format::Json,
+                "{\"updates\": {\"system\": true}}\n}\n",
+                "2:1: trailing characters",
+            ),
+        ];
+
+        for (format, contents, expected) in cases {
+            let error = parse_config::<TargetEnvironment>(Path::new("c"), contents, format, None)
+                .unwrap_err()
+                .to_string();
+
+            assert_eq!(error, format!("c:{expected}"), "{format}");
+        }
+    }
+
+    #[test]
+    fn unknown_tools_are_pointed_at_in_toml_only() {
+        let facts = Facts::fixture(DEBIAN);
+        let cases = [
+            (
+                "setup-coding.toml",
+                "[tools]\ngit = \"latest\"\nnodee = \"latest\"\n",
+                ":3:1: unknown tool `nodee`, did you mean `node`?",
+            ),
+            // YAML and JSON deserializers do not say where a map key is, so these errors only
+            // name the file
+            (
+                "setup-coding.yaml",
+                "tools:\n  git: latest\n  nodee: latest\n",
+                "setup-coding.yaml: unknown tool `nodee`, did you mean `node`?",
+            ),
+            (
+                "setup-coding.json",
+                "{\"tools\": {\"git\": \"latest\", \"nodee\": \"latest\"}}\n",
+                "setup-coding.json: unknown tool `nodee`, did you mean `node`?",
+            ),
+        ];
+
+        for (name, contents, expected) in cases {
+            let error = with_configs("unknown-tool", &[(name, contents)], |path| {
+                read_target_environment(path, Format::from_path(path), &facts)
+                    .unwrap_err()
+                    .to_string()
+            });
+
+            assert!(error.ends_with(expected), "{error}");
+        }
+    }
+
+    #[test]
+    fn read_config_file_keeps_variables_in_emails() {
+        let target_environment = with_config("raw-email", SSH_KEY, |path| {
+            read_config_file(path, Format::Toml)
+        });
+
+        assert_eq!(
+            target_environment.unwrap().keys.unwrap().ssh.unwrap().email,
+            "${env:SETUP_CODING_TEST_EMAIL:-root@vm}"
+        );
+    }
+
+    #[test]
+    fn read_target_environment_checks_emails_after_resolving_them() {
+        let facts = Facts::fixture(DEBIAN);
+        let error = with_config("resolved-email", SSH_KEY, |path| {
+            read_target_environment(path, Format::Toml, &facts)
+                .unwrap_err()
+                .to_string()
+        });
+
+        assert!(
+            error.ends_with(":4:9: `root@vm` does not look like an email address"),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn read_target_environment_checks_emails_of_overlays() {
+        let facts = Facts::fixture(DEBIAN);
+        let contents = r#"
+[hosts."dev-*".keys.ssh]
+algorithm = "ed25519"
+email = "dev"
+title = "laptop"
+"#;
+        let error = with_config("overlay-email", contents, |path| {
+            read_target_environment(path, Format::Toml, &facts)
+                .unwrap_err()
+                .to_string()
+        });
+
+        assert!(
+            error.ends_with(":4:9: `dev` does not look like an email address"),
+            "{error}"
+        );
+    }
+}
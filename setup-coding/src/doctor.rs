@@ -0,0 +1,219 @@
+use crate::error::Error;
+use crate::facts::Facts;
+use crate::state_file::StateFile;
+use std::env;
+use std::fmt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Health {
+    Ok,
+    Warning,
+    Problem,
+}
+
+impl fmt::Display for Health {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Health::Ok => write!(f, "ok"),
+            Health::Warning => write!(f, "warning"),
+            Health::Problem => write!(f, "problem"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnosis {
+    pub name: String,
+    pub health: Health,
+    pub detail: String,
+}
+
+/// A program that installers run, what for, and whether most runs need it.
+struct Program {
+    name: &'static str,
+    needed_for: &'static str,
+    required: bool,
+}
+
+const PROGRAMS: &[Program] = &[
+    Program {
+        name: "sudo",
+        needed_for: "installing packages and files outside the home directory",
+        required: true,
+    },
+    Program {
+        name: "curl",
+        needed_for: "downloading signing keys, scripts and binaries",
+        required: true,
+    },
+    Program {
+        name: "gpg",
+        needed_for: "verifying repository signing keys",
+        required: true,
+    },
+    Program {
+        name: "sha256sum",
+        needed_for: "verifying downloaded binaries",
+        required: true,
+    },
+    Program {
+        name: "ssh-keygen",
+        needed_for: "generating ssh keys",
+        required: false,
+    },
+    Program {
+        name: "ssh-add",
+        needed_for: "adding ssh keys to the agent",
+        required: false,
+    },
+    Program {
+        name: "gh",
+        needed_for: "adding ssh keys to github, the gh tool installs it",
+        required: false,
+    },
+    Program {
+        name: "snap",
+        needed_for: "tools that are installed with snap",
+        required: false,
+    },
+];
+
+/// Looks `program` up on `PATH` the way a shell would, without running it.
+fn find_program(program: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+
+    env::split_paths(&path)
+        .map(|directory| directory.join(program))
+        .find(|candidate| is_executable(candidate))
+}
+
+fn is_executable(path: &Path) -> bool {
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// The outcome of every diagnosis, shown as a table like the summary.
+#[derive(Debug, Default)]
+pub struct Report {
+    diagnoses: Vec<Diagnosis>,
+}
+
+impl Report {
+    fn add(&mut self, name: &str, health: Health, detail: impl Into<String>) {
+        self.diagnoses.push(Diagnosis {
+            name: name.to_string(),
+            health,
+            detail: detail.into(),
+        });
+    }
+
+    fn add_result<T>(
+        &mut self,
+        name: &str,
+        result: Result<T, Error>,
+        describe: impl Fn(T) -> String,
+    ) {
+        match result {
+            Ok(value) => self.add(name, Health::Ok, describe(value)),
+            Err(error) => self.add(name, Health::Problem, error.to_string()),
+        }
+    }
+
+    pub fn diagnoses(&self) -> &[Diagnosis] {
+        &self.diagnoses
+    }
+
+    pub fn has_problems(&self) -> bool {
+        self.diagnoses
+            .iter()
+            .any(|diagnosis| diagnosis.health == Health::Problem)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name_width = self
+            .diagnoses
+            .iter()
+            .map(|diagnosis| diagnosis.name.len())
+            .max()
+            .unwrap_or(0);
+
+        for diagnosis in &self.diagnoses {
+            let line = format!(
+                "{:<7}  {:<name_width$}  {}",
+                diagnosis.health.to_string(),
+                diagnosis.name,
+                diagnosis.detail,
+            );
+
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that this system is one setup-coding can work on, without changing anything.
+///
+/// `config_path` is the config that would be used, or why none was found.
+pub fn doctor(facts: &Facts, config_path: Result<PathBuf, Error>) -> Report {
+    let mut report = Report::default();
+
+    report.add_result("distribution", facts.os_release(), |os_release| {
+        let ids = os_release.ids().collect::<Vec<_>>().join(" ");
+
+        match &os_release.version_codename {
+            None => ids,
+            Some(version_codename) => format!("{ids} ({version_codename})"),
+        }
+    });
+    report.add_result(
+        "package manager",
+        facts.package_manager(),
+        |package_manager| package_manager.to_string(),
+    );
+    report.add_result("architecture", facts.deb_architecture(), |architecture| {
+        architecture.to_string()
+    });
+
+    for program in PROGRAMS {
+        match find_program(program.name) {
+            Some(path) => report.add(program.name, Health::Ok, path.display().to_string()),
+            None => {
+                let health = match program.required {
+                    true => Health::Problem,
+                    false => Health::Warning,
+                };
+
+                report.add(
+                    program.name,
+                    health,
+                    format!("not found, needed for {}", program.needed_for),
+                );
+            }
+        }
+    }
+
+    match config_path {
+        Ok(path) if path.is_file() => report.add("config", Health::Ok, path.display().to_string()),
+        Ok(path) => report.add(
+            "config",
+            Health::Problem,
+            format!("{} does not exist", path.display()),
+        ),
+        Err(error) => report.add("config", Health::Warning, error.to_string()),
+    }
+
+    report.add_result("state file", StateFile::load(), |state_file| {
+        format!(
+            "{}, {} tools recorded",
+            state_file.path().display(),
+            state_file.tools().len()
+        )
+    });
+
+    report
+}
@@ -0,0 +1,180 @@
+use crate::runner::Status;
+use semver::VersionReq;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    Spawn {
+        command: String,
+        source: io::Error,
+    },
+    NonZeroExit {
+        command: String,
+        status: Status,
+    },
+    UnreadableOutput {
+        command: String,
+        source: io::Error,
+    },
+    ReadConfig {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// `position` is the 1-based line and column of the problem, when it is known.
+    ParseConfig {
+        path: PathBuf,
+        position: Option<(usize, usize)>,
+        reason: String,
+    },
+    MissingConfig {
+        searched: Vec<PathBuf>,
+    },
+    ReadFile {
+        path: PathBuf,
+        source: io::Error,
+    },
+    WriteFile {
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// Printing what a dry run would do failed, like when stdout was closed.
+    WriteOutput(io::Error),
+    ChecksumMismatch {
+        url: String,
+        expected: String,
+        found: String,
+    },
+    FingerprintMismatch {
+        key_url: String,
+        expected: Vec<String>,
+        found: Vec<String>,
+    },
+    ParseState {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    SerializeState(toml::ser::Error),
+    SerializeConfig(Box<dyn std::error::Error + Send + Sync>),
+    InvalidConfig(String),
+    ParseRecipes(toml::de::Error),
+    InvalidRecipe {
+        tool: String,
+        reason: String,
+    },
+    UnsupportedPlatform(String),
+    MissingEnvironmentVariable(String),
+    UnresolvableVersion {
+        tool: String,
+        requirement: VersionReq,
+    },
+}
+
+impl Error {
+    /// Whether the program itself could not be found, which detection treats as "not installed".
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, Error::Spawn { source, .. } if source.kind() == io::ErrorKind::NotFound)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Spawn { command, source } => write!(f, "could not run `{command}`: {source}"),
+            Error::NonZeroExit { command, status } => write!(f, "`{command}` failed with {status}"),
+            Error::UnreadableOutput { command, source } => {
+                write!(f, "could not read the output of `{command}`: {source}")
+            }
+            Error::ReadConfig { path, source } => {
+                write!(f, "could not read {}: {source}", path.display())
+            }
+            Error::ParseConfig {
+                path,
+                position: Some((line, column)),
+                reason,
+            } => write!(f, "{}:{line}:{column}: {reason}", path.display()),
+            Error::ParseConfig {
+                path,
+                position: None,
+                reason,
+            } => write!(f, "could not parse {}: {reason}", path.display()),
+            Error::MissingConfig { searched } => write!(
+                f,
+                "no config file given and none found at {}, pass one with --config",
+                searched
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(" or ")
+            ),
+            Error::ReadFile { path, source } => {
+                write!(f, "could not read {}: {source}", path.display())
+            }
+            Error::WriteFile { path, source } => {
+                write!(f, "could not write {}: {source}", path.display())
+            }
+            Error::WriteOutput(source) => write!(f, "could not print the plan: {source}"),
+            Error::ChecksumMismatch {
+                url,
+                expected,
+                found,
+            } => write!(
+                f,
+                "the download from {url} has sha256 {found} instead of the expected {expected}"
+            ),
+            Error::FingerprintMismatch {
+                key_url,
+                expected,
+                found,
+            } => write!(
+                f,
+                "the signing key at {key_url} has the fingerprints [{}] instead of the pinned [{}]",
+                found.join(" "),
+                expected.join(" ")
+            ),
+            Error::ParseState { path, source } => {
+                write!(f, "could not parse the state file {}: {source}", path.display())
+            }
+            Error::SerializeState(source) => write!(f, "could not write the state: {source}"),
+            Error::SerializeConfig(source) => write!(f, "could not write the config: {source}"),
+            Error::InvalidConfig(reason) => write!(f, "invalid config: {reason}"),
+            Error::ParseRecipes(source) => write!(f, "could not parse the built-in recipes: {source}"),
+            Error::InvalidRecipe { tool, reason } => write!(f, "invalid recipe for {tool}: {reason}"),
+            Error::UnsupportedPlatform(reason) => write!(f, "unsupported platform: {reason}"),
+            Error::MissingEnvironmentVariable(name) => {
+                write!(f, "environment variable {name} is not set")
+            }
+            Error::UnresolvableVersion { tool, requirement } => write!(
+                f,
+                "cannot choose a version of {tool} to install for `{requirement}`, pin one like `=1.2.3`"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Spawn { source, .. }
+            | Error::UnreadableOutput { source, .. }
+            | Error::ReadConfig { source, .. }
+            | Error::ReadFile { source, .. }
+            | Error::WriteFile { source, .. }
+            | Error::WriteOutput(source) => Some(source),
+            Error::ParseRecipes(source) | Error::ParseState { source, .. } => Some(source),
+            Error::SerializeState(source) => Some(source),
+            Error::SerializeConfig(source) => Some(source.as_ref()),
+            Error::NonZeroExit { .. }
+            | Error::ParseConfig { .. }
+            | Error::MissingConfig { .. }
+            | Error::ChecksumMismatch { .. }
+            | Error::FingerprintMismatch { .. }
+            | Error::InvalidConfig(_)
+            | Error::InvalidRecipe { .. }
+            | Error::UnsupportedPlatform(_)
+            | Error::MissingEnvironmentVariable(_)
+            | Error::UnresolvableVersion { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,409 @@
+use crate::error::Error;
+use crate::package_manager::PackageManager;
+use crate::progress;
+use std::env;
+use std::ffi::CStr;
+use std::fs;
+use std::io;
+use std::mem::MaybeUninit;
+
+/// The fields of `/etc/os-release` that installers use.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct OsRelease {
+    pub id: String,
+    pub id_like: Vec<String>,
+    pub version_codename: Option<String>,
+    pub version_id: Option<String>,
+}
+
+impl OsRelease {
+    /// Parses the `KEY=value` lines of an os-release file, see os-release(5).
+    pub fn parse(contents: &str) -> OsRelease {
+        let mut os_release = OsRelease {
+            // os-release(5) defaults ID to "linux" when it is missing
+            id: "linux".to_string(),
+            ..OsRelease::default()
+        };
+        let mut ubuntu_codename = None;
+
+        for line in contents.lines() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                None => continue,
+                Some((key, value)) => (key.trim(), unquote(value.trim())),
+            };
+
+            match key {
+                "ID" => os_release.id = value,
+                "ID_LIKE" => {
+                    os_release.id_like = value.split_whitespace().map(str::to_string).collect()
+                }
+                "VERSION_CODENAME" if !value.is_empty() => {
+                    os_release.version_codename = Some(value)
+                }
+                "UBUNTU_CODENAME" if !value.is_empty() => ubuntu_codename = Some(value),
+                "VERSION_ID" => os_release.version_id = Some(value),
+                _ => {}
+            }
+        }
+
+        // older ubuntu releases only set UBUNTU_CODENAME
+        if os_release.version_codename.is_none() {
+            os_release.version_codename = ubuntu_codename;
+        }
+
+        os_release
+    }
+
+    /// `ID` followed by `ID_LIKE`, most specific first.
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.id.as_str()).chain(self.id_like.iter().map(String::as_str))
+    }
+}
+
+/// Strips shell-style quotes and backslash escapes from an os-release value.
+fn unquote(value: &str) -> String {
+    let mut unquoted = String::new();
+    let mut quote = None;
+    let mut characters = value.chars();
+
+    while let Some(character) = characters.next() {
+        match (quote, character) {
+            (None, '"' | '\'') => quote = Some(character),
+            (Some(open), _) if character == open => quote = None,
+            (Some('\''), _) => unquoted.push(character),
+            (_, '\\') => unquoted.extend(characters.next()),
+            _ => unquoted.push(character),
+        }
+    }
+
+    unquoted
+}
+
+/// The fields of uname(2) that installers use.
+#[derive(Debug)]
+pub struct Uname {
+    pub sysname: String,
+    pub nodename: String,
+    pub machine: String,
+}
+
+impl Uname {
+    pub fn read() -> io::Result<Uname> {
+        let mut utsname = MaybeUninit::<libc::utsname>::uninit();
+
+        // SAFETY: uname fills in the whole struct when it returns 0
+        let utsname = unsafe {
+            if libc::uname(utsname.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            utsname.assume_init()
+        };
+
+        let field = |field: &[libc::c_char]| {
+            // SAFETY: uname nul terminates every field
+            unsafe { CStr::from_ptr(field.as_ptr()) }
+                .to_string_lossy()
+                .into_owned()
+        };
+
+        Ok(Uname {
+            sysname: field(&utsname.sysname),
+            nodename: field(&utsname.nodename),
+            machine: field(&utsname.machine),
+        })
+    }
+}
+
+/// Maps a uname machine name to the architecture name dpkg uses.
+pub fn deb_architecture(machine: &str) -> Option<&'static str> {
+    match machine {
+        "x86_64" | "amd64" => Some("amd64"),
+        "aarch64" | "arm64" => Some("arm64"),
+        "armv7l" | "armv8l" => Some("armhf"),
+        "armv5tel" | "armv6l" => Some("armel"),
+        "i386" | "i486" | "i586" | "i686" => Some("i386"),
+        "ppc64le" => Some("ppc64el"),
+        "riscv64" => Some("riscv64"),
+        "s390x" => Some("s390x"),
+        _ => None,
+    }
+}
+
+/// Maps a uname machine name to the architecture name rpm uses.
+pub fn rpm_architecture(machine: &str) -> Option<&'static str> {
+    match machine {
+        "x86_64" | "amd64" => Some("x86_64"),
+        "aarch64" | "arm64" => Some("aarch64"),
+        "armv7l" | "armv8l" => Some("armv7hl"),
+        "armv6l" => Some("armv6hl"),
+        "i386" | "i486" | "i586" | "i686" => Some("i686"),
+        "ppc64le" => Some("ppc64le"),
+        "riscv64" => Some("riscv64"),
+        "s390x" => Some("s390x"),
+        _ => None,
+    }
+}
+
+/// The os-release of a Debian 12 machine, for tests.
+#[cfg(test)]
+pub const DEBIAN: &str = "ID=debian\nVERSION_ID=\"12\"\nVERSION_CODENAME=bookworm\n";
+
+/// What installers need to know about the system they run on.
+///
+/// Facts are read once; when reading fails the reason is kept so that only the installers
+/// that need the fact fail, instead of the whole run.
+#[derive(Debug)]
+pub struct Facts {
+    os_release: Result<OsRelease, String>,
+    uname: Result<Uname, String>,
+}
+
+fn read_os_release() -> Result<OsRelease, String> {
+    // os-release(5): /etc/os-release takes precedence over /usr/lib/os-release
+    ["/etc/os-release", "/usr/lib/os-release"]
+        .iter()
+        .find_map(|path| fs::read_to_string(path).ok())
+        .map(|contents| OsRelease::parse(&contents))
+        .ok_or_else(|| "could not read /etc/os-release or /usr/lib/os-release".to_string())
+}
+
+fn unsupported(description: &str, reason: impl std::fmt::Display) -> Error {
+    Error::UnsupportedPlatform(format!("could not determine the {description}: {reason}"))
+}
+
+impl Facts {
+    pub fn detect() -> Facts {
+        progress!("\ngetting system facts");
+
+        let os_release = read_os_release();
+        let uname = Uname::read().map_err(|error| format!("uname failed: {error}"));
+
+        match &os_release {
+            Ok(os_release) => progress!(
+                "distribution: {} {}",
+                os_release.ids().collect::<Vec<_>>().join(" "),
+                os_release.version_codename.as_deref().unwrap_or("")
+            ),
+            Err(reason) => progress!("{reason}"),
+        }
+
+        match &uname {
+            Ok(uname) => progress!("kernel: {} {}", uname.sysname, uname.machine),
+            Err(reason) => progress!("{reason}"),
+        }
+
+        Facts { os_release, uname }
+    }
+
+    /// Facts of a made-up x86_64 machine named `vm`, running the given os-release, like
+    /// [`DEBIAN`].
+    #[cfg(test)]
+    pub fn fixture(os_release: &str) -> Facts {
+        Facts {
+            os_release: Ok(OsRelease::parse(os_release)),
+            uname: Ok(Uname {
+                sysname: "Linux".to_string(),
+                nodename: "vm".to_string(),
+                machine: "x86_64".to_string(),
+            }),
+        }
+    }
+
+    pub fn os_release(&self) -> Result<&OsRelease, Error> {
+        self.os_release
+            .as_ref()
+            .map_err(|reason| unsupported("distribution", reason))
+    }
+
+    pub fn uname(&self) -> Result<&Uname, Error> {
+        self.uname
+            .as_ref()
+            .map_err(|reason| unsupported("kernel", reason))
+    }
+
+    pub fn id(&self) -> Result<&str, Error> {
+        Ok(&self.os_release()?.id)
+    }
+
+    pub fn version_codename(&self) -> Result<&str, Error> {
+        self.os_release()?
+            .version_codename
+            .as_deref()
+            .ok_or_else(|| unsupported("release name", "os-release has no VERSION_CODENAME"))
+    }
+
+    pub fn kernel_name(&self) -> Result<&str, Error> {
+        Ok(&self.uname()?.sysname)
+    }
+
+    pub fn machine_hardware_name(&self) -> Result<&str, Error> {
+        Ok(&self.uname()?.machine)
+    }
+
+    pub fn hostname(&self) -> Result<&str, Error> {
+        Ok(&self.uname()?.nodename)
+    }
+
+    pub fn deb_architecture(&self) -> Result<&'static str, Error> {
+        let machine = self.machine_hardware_name()?;
+
+        deb_architecture(machine).ok_or_else(|| unsupported("debian architecture", machine))
+    }
+
+    pub fn rpm_architecture(&self) -> Result<&'static str, Error> {
+        let machine = self.machine_hardware_name()?;
+
+        rpm_architecture(machine).ok_or_else(|| unsupported("rpm architecture", machine))
+    }
+
+    /// Whether a graphical session is running, judged by the variables it sets for its programs.
+    pub fn has_display(&self) -> bool {
+        ["DISPLAY", "WAYLAND_DISPLAY"]
+            .iter()
+            .any(|name| env::var_os(name).is_some_and(|value| !value.is_empty()))
+    }
+
+    pub fn package_manager(&self) -> Result<PackageManager, Error> {
+        let os_release = self.os_release()?;
+
+        PackageManager::from_distribution(os_release.ids()).ok_or_else(|| {
+            unsupported(
+                "package manager",
+                format!(
+                    "no backend for {}",
+                    os_release.ids().collect::<Vec<_>>().join(" ")
+                ),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> OsRelease {
+        let path = format!(
+            "{}/tests/fixtures/os-release/{name}",
+            env!("CARGO_MANIFEST_DIR")
+        );
+
+        OsRelease::parse(&fs::read_to_string(path).unwrap())
+    }
+
+    fn os_release(
+        id: &str,
+        id_like: &[&str],
+        version_codename: Option<&str>,
+        version_id: Option<&str>,
+    ) -> OsRelease {
+        OsRelease {
+            id: id.to_string(),
+            id_like: id_like.iter().map(|id| id.to_string()).collect(),
+            version_codename: version_codename.map(str::to_string),
+            version_id: version_id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn parses_debian() {
+        assert_eq!(
+            fixture("debian"),
+            os_release("debian", &[], Some("bookworm"), Some("12"))
+        );
+    }
+
+    #[test]
+    fn falls_back_to_the_ubuntu_codename() {
+        let ubuntu = fixture("ubuntu-without-version-codename");
+
+        assert_eq!(
+            ubuntu,
+            os_release("ubuntu", &["debian"], Some("xenial"), Some("16.04"))
+        );
+        assert_eq!(ubuntu.ids().collect::<Vec<_>>(), ["ubuntu", "debian"]);
+    }
+
+    #[test]
+    fn ignores_an_empty_codename() {
+        assert_eq!(
+            fixture("fedora"),
+            os_release("fedora", &[], None, Some("39"))
+        );
+    }
+
+    #[test]
+    fn parses_a_rolling_release_without_versions() {
+        assert_eq!(fixture("arch"), os_release("arch", &[], None, None));
+    }
+
+    #[test]
+    fn parses_alpine() {
+        assert_eq!(
+            fixture("alpine"),
+            os_release("alpine", &[], None, Some("3.19.1"))
+        );
+    }
+
+    #[test]
+    fn picks_the_package_manager_of_each_fixture() {
+        let cases = [
+            ("debian", PackageManager::Apt),
+            ("ubuntu-without-version-codename", PackageManager::Apt),
+            ("fedora", PackageManager::Dnf),
+            ("arch", PackageManager::Pacman),
+            ("alpine", PackageManager::Apk),
+        ];
+
+        for (name, expected) in cases {
+            let facts = Facts {
+                os_release: Ok(fixture(name)),
+                uname: Err("not needed".to_string()),
+            };
+
+            assert_eq!(facts.package_manager().unwrap(), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn prefers_version_codename_over_ubuntu_codename() {
+        // Linux Mint names its own release and the ubuntu release it is based on
+        let mint = OsRelease::parse("ID=linuxmint\nID_LIKE=\"ubuntu debian\"\nVERSION_CODENAME=virginia\nUBUNTU_CODENAME=jammy\n");
+
+        assert_eq!(mint.version_codename.as_deref(), Some("virginia"));
+    }
+
+    #[test]
+    fn defaults_the_id_to_linux() {
+        let os_release = OsRelease::parse("# no ID here\n\nNAME=Linux\nnot a field\n");
+
+        assert_eq!(os_release.id, "linux");
+    }
+
+    #[test]
+    fn unquotes_like_a_shell() {
+        let cases = [
+            ("plain", "plain"),
+            ("\"double quoted\"", "double quoted"),
+            ("'single quoted'", "single quoted"),
+            (r#""say \"hi\"""#, r#"say "hi""#),
+            (r#""back\\slash""#, r"back\slash"),
+            (r"'no \escapes'", r"no \escapes"),
+            (r"escaped\ space", "escaped space"),
+            (r#"'it'\''s'"#, "it's"),
+            ("\"a\"'b'c", "abc"),
+            ("\"\"", ""),
+        ];
+
+        for (value, expected) in cases {
+            assert_eq!(unquote(value), expected, "{value}");
+        }
+    }
+}
@@ -0,0 +1,563 @@
+use crate::condition::{retain_applicable, Excluded};
+use crate::config::{Keys, Ssh, State, ToolTarget, Updates};
+use crate::error::Error;
+use crate::facts::Facts;
+use crate::keys::{get_ssh_key_path, SSH_KEY_REQUIRES};
+use crate::progress;
+use crate::registry::Registry;
+use crate::report::Category;
+use crate::runner::Runner;
+use crate::selection::Selection;
+use crate::tools::get_tool_settings;
+use crate::updates::UPDATE_NAMES;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// One entry of the target environment, like the `docker` tool or the `ssh` key.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Node {
+    pub category: Category,
+    pub name: String,
+}
+
+impl Node {
+    fn new(category: Category, name: &str) -> Node {
+        Node {
+            category,
+            name: name.to_string(),
+        }
+    }
+
+    /// The entry that a name in `requires` stands for: an update or the key by their names,
+    /// and a tool otherwise.
+    fn resolve(name: &str) -> Node {
+        match name {
+            _ if UPDATE_NAMES.contains(&name) => Node::new(Category::Update, name),
+            "ssh" => Node::new(Category::Key, name),
+            _ => Node::new(Category::Tool, name),
+        }
+    }
+
+    /// Where the entry goes when no prerequisite says otherwise: the updates in their usual
+    /// order, then the tools by name, then the key.
+    fn rank(&self) -> (Category, usize, &str) {
+        let position = match self.category {
+            Category::Update => UPDATE_NAMES
+                .iter()
+                .position(|name| *name == self.name)
+                .unwrap_or(0),
+            _ => 0,
+        };
+
+        (self.category, position, &self.name)
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.category, self.name)
+    }
+}
+
+/// What the config asks of one entry.
+#[derive(Debug)]
+pub enum Target {
+    Update(bool),
+    Tool(ToolTarget),
+    Ssh(Ssh),
+}
+
+impl Target {
+    /// Whether the entry sets something up rather than leaving or removing it.
+    fn sets_up(&self) -> bool {
+        match self {
+            Target::Update(enabled) => *enabled,
+            Target::Tool(ToolTarget::Keyword(keyword)) => keyword != "absent",
+            Target::Tool(ToolTarget::Settings(settings)) => settings.state == State::Present,
+            Target::Ssh(_) => true,
+        }
+    }
+
+    /// Whether the entry still has something to set up, which is when its prerequisites are
+    /// needed.
+    ///
+    /// Disabled updates, tools to remove, tools that are already installed and keys that
+    /// already exist need nothing. Neither do entries that cannot be told, like an unknown
+    /// tool, since they fail when they are set up.
+    fn needs_setting_up(&self, runner: &mut dyn Runner, registry: &Registry, node: &Node) -> bool {
+        if !self.sets_up() {
+            return false;
+        }
+
+        match self {
+            Target::Update(_) => true,
+            Target::Tool(tool_target) => {
+                let recipe = registry.get(&node.name);
+                let settings = get_tool_settings(&node.name, tool_target.clone());
+
+                match (recipe, settings) {
+                    (Some(recipe), Ok(settings)) => {
+                        matches!(recipe.find(runner, settings.version.as_ref()), Ok(false))
+                    }
+                    _ => false,
+                }
+            }
+            Target::Ssh(ssh) => {
+                get_ssh_key_path(&ssh.algorithm).is_ok_and(|ssh_key_path| !ssh_key_path.exists())
+            }
+        }
+    }
+}
+
+/// Which prerequisites `plan` adds to the entries of the config.
+pub enum Prerequisites<'a> {
+    /// Those of the entries that still have something to set up, which the runner finds out.
+    Include(&'a mut dyn Runner),
+    /// None, to act only on the entries that the config names.
+    Omit,
+}
+
+/// One entry to act on, with the entries of the plan it requires, which come before it.
+#[derive(Debug)]
+pub struct Step {
+    pub node: Node,
+    pub target: Target,
+    pub prerequisites: Vec<Node>,
+}
+
+/// The entries to act on in order, and the ones whose conditions left them out.
+#[derive(Debug)]
+pub struct Plan {
+    pub excluded: Vec<Excluded>,
+    pub steps: Vec<Step>,
+}
+
+fn get_prerequisites(registry: &Registry, node: &Node) -> Result<Vec<Node>, Error> {
+    match node.category {
+        Category::Update => Ok(Vec::new()),
+        Category::Key => Ok(SSH_KEY_REQUIRES
+            .iter()
+            .map(|name| Node::resolve(name))
+            .collect()),
+        Category::Tool => {
+            // an unknown tool fails when it is set up
+            let recipe = match registry.get(&node.name) {
+                None => return Ok(Vec::new()),
+                Some(recipe) => recipe,
+            };
+
+            recipe
+                .requires
+                .iter()
+                .map(|name| {
+                    let prerequisite = Node::resolve(name);
+
+                    if prerequisite.category == Category::Tool && registry.get(name).is_none() {
+                        return Err(Error::InvalidRecipe {
+                            tool: node.name.clone(),
+                            reason: format!("requires unknown tool `{name}`"),
+                        });
+                    }
+
+                    Ok(prerequisite)
+                })
+                .collect()
+        }
+    }
+}
+
+fn collect_targets(
+    updates: Option<Updates>,
+    tools: Option<BTreeMap<String, ToolTarget>>,
+    keys: Option<Keys>,
+) -> BTreeMap<Node, Target> {
+    let mut targets = BTreeMap::new();
+
+    if let Some(updates) = updates {
+        for name in UPDATE_NAMES {
+            if let Some(enabled) = updates.get(name) {
+                targets.insert(Node::new(Category::Update, name), Target::Update(enabled));
+            }
+        }
+    }
+
+    for (name, tool_target) in tools.unwrap_or_default() {
+        targets.insert(Node::new(Category::Tool, &name), Target::Tool(tool_target));
+    }
+
+    if let Some(ssh) = keys.and_then(|keys| keys.ssh) {
+        targets.insert(Node::new(Category::Key, "ssh"), Target::Ssh(ssh));
+    }
+
+    targets
+}
+
+fn absent_error(node: &Node, prerequisite: &Node) -> Error {
+    Error::InvalidConfig(format!(
+        "{node} requires {prerequisite}, which is absent in the config"
+    ))
+}
+
+/// Adds the prerequisites that the targets leave out, with their settings from the config
+/// when it has them, unless `--skip` or their own conditions keep them out.
+///
+/// Only the entries in `setting_up` need their prerequisites, and the added ones that still
+/// have something to set up join it.
+fn include_prerequisites(
+    runner: &mut dyn Runner,
+    registry: &Registry,
+    selection: &Selection,
+    excluded: &[Excluded],
+    mut configured: BTreeMap<Node, Target>,
+    targets: &mut BTreeMap<Node, Target>,
+    setting_up: &mut BTreeSet<Node>,
+) -> Result<(), Error> {
+    let mut pending = setting_up.iter().cloned().collect::<Vec<_>>();
+
+    while let Some(node) = pending.pop() {
+        for prerequisite in get_prerequisites(registry, &node)? {
+            if let Some(target) = targets.get(&prerequisite) {
+                if prerequisite.category == Category::Tool && !target.sets_up() {
+                    return Err(absent_error(&node, &prerequisite));
+                }
+
+                continue;
+            }
+
+            let is_excluded = excluded.iter().any(|excluded| {
+                excluded.category == prerequisite.category && excluded.name == prerequisite.name
+            });
+
+            if is_excluded || selection.skips(prerequisite.category, &prerequisite.name) {
+                continue;
+            }
+
+            let target = match configured.remove(&prerequisite) {
+                // turned off on purpose, the dependent goes ahead without it
+                Some(Target::Update(false)) => continue,
+                Some(target) if !target.sets_up() => {
+                    return Err(absent_error(&node, &prerequisite))
+                }
+                Some(target) => target,
+                None => match prerequisite.category {
+                    Category::Update => Target::Update(true),
+                    Category::Tool => Target::Tool(ToolTarget::Keyword("latest".to_string())),
+                    Category::Key => {
+                        return Err(Error::InvalidConfig(format!(
+                            "{node} requires {prerequisite}, which is not in the config"
+                        )))
+                    }
+                },
+            };
+
+            progress!("including {prerequisite}, required by {node}");
+
+            if target.needs_setting_up(runner, registry, &prerequisite) {
+                setting_up.insert(prerequisite.clone());
+                pending.push(prerequisite.clone());
+            }
+
+            targets.insert(prerequisite, target);
+        }
+    }
+
+    Ok(())
+}
+
+/// Puts `node` into `order` after its prerequisites, depth first.
+///
+/// `visiting` holds the entries on the way to `node`, to report a cycle through them.
+fn visit(
+    registry: &Registry,
+    targets: &BTreeMap<Node, Target>,
+    setting_up: &BTreeSet<Node>,
+    node: &Node,
+    visiting: &mut Vec<Node>,
+    order: &mut Vec<(Node, Vec<Node>)>,
+) -> Result<(), Error> {
+    if order.iter().any(|(visited, _)| visited == node) {
+        return Ok(());
+    }
+
+    if let Some(start) = visiting.iter().position(|visited| visited == node) {
+        let cycle = visiting[start..]
+            .iter()
+            .chain([node])
+            .map(|node| node.to_string())
+            .collect::<Vec<_>>();
+
+        return Err(Error::InvalidConfig(format!(
+            "the prerequisites form a cycle: {}",
+            cycle.join(" -> ")
+        )));
+    }
+
+    // only the prerequisites that are part of the plan order it
+    let mut prerequisites = match setting_up.contains(node) {
+        false => Vec::new(),
+        true => get_prerequisites(registry, node)?
+            .into_iter()
+            .filter(|prerequisite| targets.contains_key(prerequisite))
+            .collect(),
+    };
+
+    prerequisites.sort_by(|left, right| left.rank().cmp(&right.rank()));
+
+    visiting.push(node.clone());
+
+    for prerequisite in &prerequisites {
+        visit(registry, targets, setting_up, prerequisite, visiting, order)?;
+    }
+
+    visiting.pop();
+    order.push((node.clone(), prerequisites));
+
+    Ok(())
+}
+
+/// Decides what to act on and in which order.
+///
+/// The entries are narrowed down by `selection` and by their `when` and `hosts` conditions,
+/// then what they require is added as `prerequisites` says, and each entry that still has
+/// something to set up is ordered after its prerequisites. Prerequisites that form a cycle
+/// are an error.
+pub fn plan(
+    registry: &Registry,
+    facts: &Facts,
+    selection: &Selection,
+    updates: Option<Updates>,
+    tools: Option<BTreeMap<String, ToolTarget>>,
+    keys: Option<Keys>,
+    prerequisites: Prerequisites,
+) -> Result<Plan, Error> {
+    let configured = collect_targets(updates.clone(), tools.clone(), keys.clone());
+    let (mut updates, mut tools, mut keys) = (updates, tools, keys);
+
+    selection.retain(&mut updates, &mut tools, &mut keys);
+
+    let excluded = retain_applicable(facts, &mut updates, &mut tools, &mut keys);
+    let mut targets = collect_targets(updates, tools, keys);
+    let mut setting_up = BTreeSet::new();
+
+    match prerequisites {
+        Prerequisites::Include(runner) => {
+            for (node, target) in &targets {
+                if target.needs_setting_up(runner, registry, node) {
+                    setting_up.insert(node.clone());
+                }
+            }
+
+            include_prerequisites(
+                runner,
+                registry,
+                selection,
+                &excluded,
+                configured,
+                &mut targets,
+                &mut setting_up,
+            )?;
+        }
+        Prerequisites::Omit => {
+            setting_up.extend(
+                targets
+                    .iter()
+                    .filter(|(_, target)| target.sets_up())
+                    .map(|(node, _)| node.clone()),
+            );
+        }
+    }
+
+    let mut roots = targets.keys().cloned().collect::<Vec<_>>();
+    let mut order = Vec::new();
+
+    roots.sort_by(|left, right| left.rank().cmp(&right.rank()));
+
+    for root in &roots {
+        visit(
+            registry,
+            &targets,
+            &setting_up,
+            root,
+            &mut Vec::new(),
+            &mut order,
+        )?;
+    }
+
+    let steps = order
+        .into_iter()
+        .map(|(node, prerequisites)| Step {
+            target: targets
+                .remove(&node)
+                .expect("only the entries of the plan are ordered"),
+            node,
+            prerequisites,
+        })
+        .collect();
+
+    Ok(Plan { excluded, steps })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::facts::DEBIAN;
+    use crate::recipe::Recipe;
+    use crate::runner::ScriptedRunner;
+
+    /// A tool installed by a script, which `--version` finds.
+    fn recipe(name: &str, requires: &[&str]) -> Recipe {
+        toml::from_str(&format!(
+            r#"
+name = "{name}"
+requires = {requires:?}
+detect = [{{ command = ["{name}", "--version"] }}]
+install = [{{ type = "script", url = "https://example.com/{name}" }}]
+"#
+        ))
+        .unwrap()
+    }
+
+    fn registry(recipes: &[(&str, &[&str])]) -> Registry {
+        let mut registry = Registry::builtin().unwrap();
+
+        for (name, requires) in recipes {
+            registry.add(recipe(name, requires)).unwrap();
+        }
+
+        registry
+    }
+
+    fn latest(names: &[&str]) -> Option<BTreeMap<String, ToolTarget>> {
+        Some(
+            names
+                .iter()
+                .map(|name| (name.to_string(), ToolTarget::Keyword("latest".to_string())))
+                .collect(),
+        )
+    }
+
+    fn plan_tools(
+        registry: &Registry,
+        runner: &mut ScriptedRunner,
+        selection: &Selection,
+        tools: Option<BTreeMap<String, ToolTarget>>,
+    ) -> Result<Vec<String>, Error> {
+        let plan = plan(
+            registry,
+            &Facts::fixture(DEBIAN),
+            selection,
+            None,
+            tools,
+            None,
+            Prerequisites::Include(runner),
+        )?;
+
+        Ok(plan
+            .steps
+            .iter()
+            .map(|step| step.node.to_string())
+            .collect())
+    }
+
+    #[test]
+    fn prerequisites_come_first() {
+        let registry = registry(&[("alpha", &["omega"]), ("omega", &["zeta"]), ("zeta", &[])]);
+
+        let order = plan_tools(
+            &registry,
+            &mut ScriptedRunner::new(),
+            &Selection::default(),
+            latest(&["alpha", "omega", "zeta"]),
+        );
+
+        assert_eq!(order.unwrap(), ["tool zeta", "tool omega", "tool alpha"]);
+    }
+
+    #[test]
+    fn prerequisites_that_form_a_cycle_are_an_error() {
+        let registry = registry(&[("a", &["b"]), ("b", &["a"])]);
+
+        let error = plan_tools(
+            &registry,
+            &mut ScriptedRunner::new(),
+            &Selection::default(),
+            latest(&["a", "b"]),
+        );
+
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "invalid config: the prerequisites form a cycle: tool a -> tool b -> tool a"
+        );
+    }
+
+    #[test]
+    fn missing_prerequisites_are_included() {
+        let registry = Registry::builtin().unwrap();
+
+        let order = plan_tools(
+            &registry,
+            &mut ScriptedRunner::new(),
+            &Selection::default(),
+            latest(&["docker"]),
+        );
+
+        assert_eq!(
+            order.unwrap(),
+            [
+                "tool ca_certificates",
+                "tool curl",
+                "tool gnupg",
+                "tool docker"
+            ]
+        );
+    }
+
+    #[test]
+    fn prerequisites_of_installed_tools_are_left_out() {
+        let registry = Registry::builtin().unwrap();
+
+        let order = plan_tools(
+            &registry,
+            &mut ScriptedRunner::new().on_query("docker --version", "Docker version 24.0.5\n"),
+            &Selection::default(),
+            latest(&["docker"]),
+        );
+
+        assert_eq!(order.unwrap(), ["tool docker"]);
+    }
+
+    #[test]
+    fn skipped_prerequisites_are_left_out() {
+        let registry = Registry::builtin().unwrap();
+        let selection = Selection {
+            skip: vec!["curl".to_string(), "gnupg".to_string()],
+            ..Selection::default()
+        };
+
+        let order = plan_tools(
+            &registry,
+            &mut ScriptedRunner::new(),
+            &selection,
+            latest(&["docker"]),
+        );
+
+        assert_eq!(order.unwrap(), ["tool ca_certificates", "tool docker"]);
+    }
+
+    #[test]
+    fn omitted_prerequisites_are_not_included() {
+        let plan = plan(
+            &Registry::builtin().unwrap(),
+            &Facts::fixture(DEBIAN),
+            &Selection::default(),
+            None,
+            latest(&["docker_compose"]),
+            None,
+            Prerequisites::Omit,
+        )
+        .unwrap();
+
+        assert_eq!(plan.steps.len(), 1);
+        assert_eq!(plan.steps[0].node.name, "docker_compose");
+    }
+}
@@ -0,0 +1,217 @@
+use crate::check::check_dependencies;
+use crate::config::{is_email, Keys, Ssh, TargetEnvironment, ToolSettings, ToolTarget, Updates};
+use crate::error::Error;
+use crate::facts::Facts;
+use crate::keys::{ssh_key_path_in, SSH_ALGORITHMS};
+use crate::progress;
+use crate::registry::Registry;
+use crate::runner::{CommandLine, Runner};
+use semver::VersionReq;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Every built-in tool that is installed, pinned to the installed version when its recipe
+/// can read one, so that applying the config reproduces this machine.
+fn detect_tools(
+    runner: &mut dyn Runner,
+    registry: &Registry,
+) -> Result<BTreeMap<String, ToolTarget>, Error> {
+    let mut tools = BTreeMap::new();
+
+    for recipe in registry.recipes() {
+        if !recipe.find(runner, None)? {
+            continue;
+        }
+
+        let tool_target = match recipe.installed_version(runner)? {
+            None => ToolTarget::Keyword("latest".to_string()),
+            Some(version) => ToolTarget::Settings(ToolSettings {
+                version: Some(
+                    VersionReq::parse(&format!("={version}"))
+                        .expect("an exact requirement on a parsed version is valid"),
+                ),
+                ..ToolSettings::default()
+            }),
+        };
+
+        tools.insert(recipe.name.clone(), tool_target);
+    }
+
+    Ok(tools)
+}
+
+/// The first existing key of a supported type in `home`, with the email taken from the
+/// comment of its public key or else from git, whichever looks like an email address.
+fn detect_ssh_key(
+    runner: &mut dyn Runner,
+    facts: &Facts,
+    home: &Path,
+) -> Result<Option<Ssh>, Error> {
+    for algorithm in SSH_ALGORITHMS {
+        let ssh_key_path = ssh_key_path_in(home, algorithm);
+
+        if !ssh_key_path.exists() {
+            continue;
+        }
+
+        progress!("found ssh key: {}", ssh_key_path.display());
+
+        let public_key_path = ssh_key_path.with_extension("pub");
+        let comment = runner.read_file(&public_key_path)?.and_then(|public_key| {
+            // ssh-ed25519 AAAAC3Nza... your_email@example.com
+            let comment = public_key.split_whitespace().skip(2).collect::<Vec<_>>();
+
+            (!comment.is_empty()).then(|| comment.join(" "))
+        });
+
+        let email = match comment.filter(|comment| is_email(comment)) {
+            Some(comment) => comment,
+            None => {
+                // git config --global user.email
+                let command = CommandLine::new("git").args(["config", "--global", "user.email"]);
+
+                match runner.query(&command) {
+                    Ok(output) if output.status.is_success() && is_email(output.stdout.trim()) => {
+                        output.stdout.trim().to_string()
+                    }
+                    // a placeholder that passes validation, like in the examples
+                    _ => "your_email@example.com".to_string(),
+                }
+            }
+        };
+
+        return Ok(Some(Ssh {
+            algorithm: algorithm.to_string(),
+            email,
+            title: facts.hostname().unwrap_or_default().to_string(),
+            when: None,
+            hosts: Vec::new(),
+        }));
+    }
+
+    Ok(None)
+}
+
+/// Describes the current machine, with the keys of the user whose home is `home`, as a
+/// target environment, using only read-only queries.
+pub fn init(
+    runner: &mut dyn Runner,
+    facts: &Facts,
+    home: &Path,
+) -> Result<TargetEnvironment, Error> {
+    let registry = Registry::builtin()?;
+
+    progress!("\nchecking updates");
+
+    let dependencies = matches!(check_dependencies(runner, facts), Ok(None));
+
+    progress!("\nchecking tools");
+
+    let tools = detect_tools(runner, &registry)?;
+
+    progress!("\nchecking keys");
+
+    let ssh = detect_ssh_key(runner, facts, home)?;
+
+    Ok(TargetEnvironment {
+        keys: ssh.map(|ssh| Keys { ssh: Some(ssh) }),
+        tools: Some(tools),
+        updates: Some(Updates {
+            cleanup: Some(true),
+            dependencies: Some(dependencies),
+            system: Some(true),
+            ..Updates::default()
+        }),
+        ..TargetEnvironment::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{format_config, read_target_environment, Format};
+    use crate::facts::DEBIAN;
+    use crate::runner::ScriptedRunner;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+
+    // ssh-keygen comments keys with user@host unless told otherwise
+    const PUBLIC_KEY: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAI root@vm\n";
+
+    /// Runs `test` with a fresh home directory holding an ed25519 key.
+    fn with_home<T>(name: &str, test: impl FnOnce(&Path, PathBuf) -> T) -> T {
+        let home = env::temp_dir().join(format!("setup-coding-{name}-{}", std::process::id()));
+        let ssh_key_path = home.join(".ssh").join("id_ed25519");
+
+        fs::create_dir_all(ssh_key_path.parent().unwrap()).unwrap();
+        fs::write(&ssh_key_path, "").unwrap();
+
+        let result = test(&home, ssh_key_path.with_extension("pub"));
+
+        fs::remove_dir_all(&home).unwrap();
+
+        result
+    }
+
+    #[test]
+    fn init_output_parses_with_a_key_comment_that_is_no_email() {
+        let facts = Facts::fixture(DEBIAN);
+
+        let (target_environment, parsed) = with_home("init", |home, public_key_path| {
+            let mut runner =
+                ScriptedRunner::new().on_file(public_key_path.to_str().unwrap(), PUBLIC_KEY);
+            let target_environment = init(&mut runner, &facts, home).unwrap();
+            let config_path = home.join("setup-coding.toml");
+
+            fs::write(
+                &config_path,
+                format_config(&target_environment, Format::Toml).unwrap(),
+            )
+            .unwrap();
+
+            let parsed = read_target_environment(&config_path, Format::Toml, &facts);
+
+            (target_environment, parsed)
+        });
+
+        let ssh = target_environment.keys.unwrap().ssh.unwrap();
+
+        assert_eq!(ssh.email, "your_email@example.com");
+        assert_eq!(ssh.title, "vm");
+        assert_eq!(
+            parsed.unwrap().keys.unwrap().ssh.unwrap().email,
+            "your_email@example.com"
+        );
+    }
+
+    #[test]
+    fn ssh_key_email_falls_back_to_git() {
+        let facts = Facts::fixture(DEBIAN);
+
+        let ssh = with_home("git", |home, public_key_path| {
+            let mut runner = ScriptedRunner::new()
+                .on_file(public_key_path.to_str().unwrap(), PUBLIC_KEY)
+                .on_query("git config --global user.email", "dev@example.org\n");
+
+            detect_ssh_key(&mut runner, &facts, home)
+        });
+
+        assert_eq!(ssh.unwrap().unwrap().email, "dev@example.org");
+    }
+
+    #[test]
+    fn ssh_key_email_skips_a_git_email_that_is_no_email() {
+        let facts = Facts::fixture(DEBIAN);
+
+        let ssh = with_home("placeholder", |home, public_key_path| {
+            let mut runner = ScriptedRunner::new()
+                .on_file(public_key_path.to_str().unwrap(), PUBLIC_KEY)
+                .on_query("git config --global user.email", "root@vm\n");
+
+            detect_ssh_key(&mut runner, &facts, home)
+        });
+
+        assert_eq!(ssh.unwrap().unwrap().email, "your_email@example.com");
+    }
+}
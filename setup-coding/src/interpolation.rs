@@ -0,0 +1,575 @@
+use crate::condition::{get_string_fact, STRING_FACTS};
+use crate::config::did_you_mean;
+use crate::facts::Facts;
+use serde::de::{
+    self, DeserializeSeed, Deserializer, EnumAccess, MapAccess, SeqAccess, VariantAccess, Visitor,
+};
+use std::env;
+use std::fmt;
+
+/// What `${env:NAME}` and `${fact:NAME}` in a config resolve to.
+#[derive(Clone, Copy)]
+pub struct Variables<'a> {
+    facts: &'a Facts,
+}
+
+impl<'a> Variables<'a> {
+    pub fn new(facts: &'a Facts) -> Variables<'a> {
+        Variables { facts }
+    }
+
+    /// Replaces every `${namespace:NAME}` or `${namespace:NAME:-default}` in `text`.
+    ///
+    /// The default is taken when the variable is unset or empty; `$${` stands for a literal `${`.
+    pub fn interpolate(&self, text: &str) -> Result<String, String> {
+        let mut interpolated = String::with_capacity(text.len());
+        let mut rest = text;
+
+        while let Some(start) = rest.find('$') {
+            interpolated.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            if let Some(after) = rest.strip_prefix("$${") {
+                interpolated.push_str("${");
+                rest = after;
+                continue;
+            }
+
+            let after = match rest.strip_prefix("${") {
+                None => {
+                    interpolated.push('$');
+                    rest = &rest[1..];
+                    continue;
+                }
+                Some(after) => after,
+            };
+
+            let end = after
+                .find('}')
+                .ok_or_else(|| format!("`${{{after}` is missing its closing `}}`"))?;
+
+            interpolated.push_str(&self.resolve(&after[..end])?);
+            rest = &after[end + 1..];
+        }
+
+        interpolated.push_str(rest);
+
+        Ok(interpolated)
+    }
+
+    fn resolve(&self, variable: &str) -> Result<String, String> {
+        let (name, default) = match variable.split_once(":-") {
+            None => (variable, None),
+            Some((name, default)) => (name, Some(default)),
+        };
+
+        let (value, reason) = match name.split_once(':') {
+            Some(("env", key)) => match env::var(key) {
+                Ok(value) if !value.is_empty() => (Some(value), String::new()),
+                Ok(_) => (None, format!("{key} is empty")),
+                Err(_) => (None, format!("{key} is not set")),
+            },
+            Some(("fact", fact)) if STRING_FACTS.contains(&fact) => {
+                match get_string_fact(self.facts, fact) {
+                    Ok(value) => (Some(value), String::new()),
+                    Err(error) => (None, error.to_string()),
+                }
+            }
+            Some(("fact", fact)) => {
+                return Err(format!(
+                    "unknown fact `{fact}` in `${{{variable}}}`{}",
+                    did_you_mean(fact, STRING_FACTS.iter().copied())
+                ))
+            }
+            _ => {
+                return Err(format!(
+                    "unknown variable `${{{variable}}}`, use `${{env:NAME}}` or `${{fact:NAME}}`"
+                ))
+            }
+        };
+
+        match (value, default) {
+            (Some(value), _) => Ok(value),
+            (None, Some(default)) => Ok(default.to_string()),
+            (None, None) => Err(format!(
+                "unresolved variable `${{{name}}}`: {reason}; give a default like `${{{name}:-value}}`"
+            )),
+        }
+    }
+}
+
+/// Wraps a deserializer of any format so that every string value it hands out is interpolated
+/// with `variables`, and an unresolved variable is an error at that value.
+///
+/// Keys and field names are left alone, but variant names are not, since a variant like
+/// `state = "absent"` is written as a value. The same wrapper is reused for the visitors,
+/// seeds and accessors that the wrapped deserializer passes around.
+pub struct Interpolating<'a, T> {
+    inner: T,
+    variables: Variables<'a>,
+}
+
+impl<'a, T> Interpolating<'a, T> {
+    pub fn new(inner: T, variables: Variables<'a>) -> Interpolating<'a, T> {
+        Interpolating { inner, variables }
+    }
+
+    fn wrap<U>(&self, inner: U) -> Interpolating<'a, U> {
+        Interpolating::new(inner, self.variables)
+    }
+}
+
+macro_rules! forward_deserialize {
+    ($($method:ident($($argument:ident: $type:ty),*);)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, $($argument: $type,)* visitor: V) -> Result<V::Value, D::Error> {
+                let visitor = self.wrap(visitor);
+
+                self.inner.$method($($argument,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, D: Deserializer<'de>> Deserializer<'de> for Interpolating<'_, D> {
+    type Error = D::Error;
+
+    forward_deserialize! {
+        deserialize_any();
+        deserialize_bool();
+        deserialize_i8();
+        deserialize_i16();
+        deserialize_i32();
+        deserialize_i64();
+        deserialize_i128();
+        deserialize_u8();
+        deserialize_u16();
+        deserialize_u32();
+        deserialize_u64();
+        deserialize_u128();
+        deserialize_f32();
+        deserialize_f64();
+        deserialize_char();
+        deserialize_str();
+        deserialize_string();
+        deserialize_bytes();
+        deserialize_byte_buf();
+        deserialize_option();
+        deserialize_unit();
+        deserialize_unit_struct(name: &'static str);
+        deserialize_newtype_struct(name: &'static str);
+        deserialize_seq();
+        deserialize_tuple(len: usize);
+        deserialize_tuple_struct(name: &'static str, len: usize);
+        deserialize_map();
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]);
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]);
+        deserialize_identifier();
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        self.inner.deserialize_ignored_any(visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+macro_rules! forward_visit {
+    ($($method:ident($type:ty);)*) => {
+        $(
+            fn $method<E: de::Error>(self, value: $type) -> Result<V::Value, E> {
+                self.inner.$method(value)
+            }
+        )*
+    };
+}
+
+impl<'de, V: Visitor<'de>> Visitor<'de> for Interpolating<'_, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.inner.expecting(f)
+    }
+
+    forward_visit! {
+        visit_bool(bool);
+        visit_i8(i8);
+        visit_i16(i16);
+        visit_i32(i32);
+        visit_i64(i64);
+        visit_i128(i128);
+        visit_u8(u8);
+        visit_u16(u16);
+        visit_u32(u32);
+        visit_u64(u64);
+        visit_u128(u128);
+        visit_f32(f32);
+        visit_f64(f64);
+        visit_char(char);
+        visit_bytes(&[u8]);
+        visit_borrowed_bytes(&'de [u8]);
+        visit_byte_buf(Vec<u8>);
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<V::Value, E> {
+        if !value.contains('$') {
+            return self.inner.visit_str(value);
+        }
+
+        let value = self.variables.interpolate(value).map_err(E::custom)?;
+
+        self.inner.visit_string(value)
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, value: &'de str) -> Result<V::Value, E> {
+        if !value.contains('$') {
+            return self.inner.visit_borrowed_str(value);
+        }
+
+        self.visit_str(value)
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<V::Value, E> {
+        if !value.contains('$') {
+            return self.inner.visit_string(value);
+        }
+
+        self.visit_str(&value)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<V::Value, E> {
+        self.inner.visit_none()
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<V::Value, E> {
+        self.inner.visit_unit()
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<V::Value, D::Error> {
+        let deserializer = self.wrap(deserializer);
+
+        self.inner.visit_some(deserializer)
+    }
+
+    fn visit_newtype_struct<D: Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> Result<V::Value, D::Error> {
+        let deserializer = self.wrap(deserializer);
+
+        self.inner.visit_newtype_struct(deserializer)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<V::Value, A::Error> {
+        let seq = self.wrap(seq);
+
+        self.inner.visit_seq(seq)
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+        let map = self.wrap(map);
+
+        self.inner.visit_map(map)
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<V::Value, A::Error> {
+        let data = self.wrap(data);
+
+        self.inner.visit_enum(data)
+    }
+}
+
+impl<'de, T: DeserializeSeed<'de>> DeserializeSeed<'de> for Interpolating<'_, T> {
+    type Value = T::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<T::Value, D::Error> {
+        let deserializer = self.wrap(deserializer);
+
+        self.inner.deserialize(deserializer)
+    }
+}
+
+impl<'de, A: SeqAccess<'de>> SeqAccess<'de> for Interpolating<'_, A> {
+    type Error = A::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, A::Error> {
+        let seed = self.wrap(seed);
+
+        self.inner.next_element_seed(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'de, A: MapAccess<'de>> MapAccess<'de> for Interpolating<'_, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        self.inner.next_key_seed(seed)
+    }
+
+    fn next_value_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<T::Value, A::Error> {
+        let seed = self.wrap(seed);
+
+        self.inner.next_value_seed(seed)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'a, 'de, A: EnumAccess<'de>> EnumAccess<'de> for Interpolating<'a, A> {
+    type Error = A::Error;
+    type Variant = Interpolating<'a, A::Variant>;
+
+    fn variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<(T::Value, Self::Variant), A::Error> {
+        let variables = self.variables;
+        let seed = self.wrap(seed);
+        let (value, variant) = self.inner.variant_seed(seed)?;
+
+        Ok((value, Interpolating::new(variant, variables)))
+    }
+}
+
+impl<'de, A: VariantAccess<'de>> VariantAccess<'de> for Interpolating<'_, A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        self.inner.unit_variant()
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, A::Error> {
+        let seed = self.wrap(seed);
+
+        self.inner.newtype_variant_seed(seed)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+        let visitor = self.wrap(visitor);
+
+        self.inner.tuple_variant(len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        let visitor = self.wrap(visitor);
+
+        self.inner.struct_variant(fields, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::facts::DEBIAN;
+    use serde::Deserialize;
+    use serde_derive::Deserialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    enum State {
+        Present,
+        Absent,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(tag = "type", rename_all = "lowercase")]
+    enum Method {
+        Script { url: String },
+        Snap { snap: String },
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(untagged)]
+    enum Target {
+        Keyword(String),
+        Settings { version: String },
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Config {
+        names: Vec<String>,
+        labels: BTreeMap<String, String>,
+        method: Method,
+        targets: BTreeMap<String, Target>,
+        state: State,
+    }
+
+    const CONFIG: &str = r#"
+names = ["${fact:id}", "$${fact:id}"]
+labels = { "${fact:id}" = "${fact:release}" }
+method = { type = "${env:SETUP_CODING_TEST_METHOD:-snap}", snap = "code-${fact:arch}" }
+targets = { node = "${env:SETUP_CODING_TEST_NODE:-latest}", go = { version = "^${env:SETUP_CODING_TEST_GO:-1}" } }
+state = "${env:SETUP_CODING_TEST_STATE:-absent}"
+"#;
+
+    fn interpolate(text: &str) -> Result<String, String> {
+        Variables::new(&Facts::fixture(DEBIAN)).interpolate(text)
+    }
+
+    #[test]
+    fn variables_resolve_to_their_values_or_defaults() {
+        env::set_var("SETUP_CODING_TEST_SET", "value");
+        env::set_var("SETUP_CODING_TEST_EMPTY", "");
+
+        let cases = [
+            ("plain", Ok("plain")),
+            ("costs $5", Ok("costs $5")),
+            ("$${env:HOME}", Ok("${env:HOME}")),
+            ("a$${b}c", Ok("a${b}c")),
+            ("${fact:id}", Ok("debian")),
+            ("${fact:release}-${fact:arch}", Ok("bookworm-amd64")),
+            ("${env:SETUP_CODING_TEST_SET}", Ok("value")),
+            ("${env:SETUP_CODING_TEST_SET:-default}", Ok("value")),
+            ("${env:SETUP_CODING_TEST_UNSET:-default}", Ok("default")),
+            ("${env:SETUP_CODING_TEST_EMPTY:-default}", Ok("default")),
+            ("${env:SETUP_CODING_TEST_UNSET:-}", Ok("")),
+            (
+                "${env:SETUP_CODING_TEST_UNSET}",
+                Err("unresolved variable `${env:SETUP_CODING_TEST_UNSET}`: SETUP_CODING_TEST_UNSET is not set; give a default like `${env:SETUP_CODING_TEST_UNSET:-value}`"),
+            ),
+            (
+                "${env:SETUP_CODING_TEST_EMPTY}",
+                Err("unresolved variable `${env:SETUP_CODING_TEST_EMPTY}`: SETUP_CODING_TEST_EMPTY is empty; give a default like `${env:SETUP_CODING_TEST_EMPTY:-value}`"),
+            ),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(
+                interpolate(text),
+                expected.map(str::to_string).map_err(str::to_string),
+                "{text}"
+            );
+        }
+
+        env::remove_var("SETUP_CODING_TEST_SET");
+        env::remove_var("SETUP_CODING_TEST_EMPTY");
+    }
+
+    #[test]
+    fn malformed_variables_are_rejected() {
+        let cases = [
+            (
+                "${HOME}",
+                "unknown variable `${HOME}`, use `${env:NAME}` or `${fact:NAME}`",
+            ),
+            (
+                "${user:name}",
+                "unknown variable `${user:name}`, use `${env:NAME}` or `${fact:NAME}`",
+            ),
+            (
+                "${fact:hostnme}",
+                "unknown fact `hostnme` in `${fact:hostnme}`, did you mean `hostname`?",
+            ),
+            (
+                "${fact:colour}",
+                "unknown fact `colour` in `${fact:colour}`",
+            ),
+            ("${env:HOME", "`${env:HOME` is missing its closing `}`"),
+            (
+                "ok ${fact:id} ${env:HOME",
+                "`${env:HOME` is missing its closing `}`",
+            ),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(interpolate(text), Err(expected.to_string()), "{text}");
+        }
+    }
+
+    #[test]
+    fn every_string_value_is_interpolated() {
+        let facts = Facts::fixture(DEBIAN);
+        let deserializer = toml::Deserializer::new(CONFIG);
+
+        let config = Config::deserialize(Interpolating::new(deserializer, Variables::new(&facts)));
+
+        assert_eq!(
+            config.unwrap(),
+            Config {
+                names: vec!["debian".to_string(), "${fact:id}".to_string()],
+                // keys are left alone
+                labels: BTreeMap::from([("${fact:id}".to_string(), "bookworm".to_string())]),
+                method: Method::Snap {
+                    snap: "code-amd64".to_string()
+                },
+                targets: BTreeMap::from([
+                    (
+                        "go".to_string(),
+                        Target::Settings {
+                            version: "^1".to_string()
+                        }
+                    ),
+                    ("node".to_string(), Target::Keyword("latest".to_string())),
+                ]),
+                state: State::Absent,
+            }
+        );
+    }
+
+    #[test]
+    fn variants_are_interpolated_in_every_format() {
+        let facts = Facts::fixture(DEBIAN);
+        let variables = Variables::new(&facts);
+        let state = "${env:SETUP_CODING_TEST_STATE:-absent}";
+
+        let toml = State::deserialize(Interpolating::new(
+            toml::de::ValueDeserializer::new(&format!("\"{state}\"")),
+            variables,
+        ));
+        let yaml = State::deserialize(Interpolating::new(
+            serde_yaml_ng::Deserializer::from_str(state),
+            variables,
+        ));
+        let json = State::deserialize(Interpolating::new(
+            &mut serde_json::Deserializer::from_str(&format!("\"{state}\"")),
+            variables,
+        ));
+
+        assert_eq!(toml.unwrap(), State::Absent);
+        assert_eq!(yaml.unwrap(), State::Absent);
+        assert_eq!(json.unwrap(), State::Absent);
+    }
+
+    #[test]
+    fn unresolved_variables_are_errors_at_their_value() {
+        let facts = Facts::fixture(DEBIAN);
+        let text = "names = [\"${fact:id}\", \"${env:SETUP_CODING_TEST_UNSET}\"]\n";
+        let deserializer = toml::Deserializer::new(text);
+
+        let error = BTreeMap::<String, Vec<String>>::deserialize(Interpolating::new(
+            deserializer,
+            Variables::new(&facts),
+        ))
+        .unwrap_err();
+
+        assert!(
+            error
+                .message()
+                .starts_with("unresolved variable `${env:SETUP_CODING_TEST_UNSET}`"),
+            "{error}"
+        );
+        assert_eq!(
+            &text[error.span().unwrap()],
+            "\"${env:SETUP_CODING_TEST_UNSET}\""
+        );
+    }
+}
@@ -0,0 +1,244 @@
+use crate::error::Error;
+use crate::progress;
+use crate::run_step;
+use crate::runner::{CommandLine, Runner};
+use crate::verbose;
+use std::path::Path;
+
+/// Where apt keyrings for third-party repositories belong, see sources.list(5).
+const KEYRING_DIRECTORIES: [&str; 2] = ["/usr/share/keyrings/", "/etc/apt/keyrings/"];
+
+pub fn is_keyring_path(path: &str) -> bool {
+    KEYRING_DIRECTORIES
+        .iter()
+        .any(|directory| path.starts_with(directory))
+}
+
+/// Uppercase hex without spaces, the way gpg prints fingerprints in `--with-colons` output.
+pub fn normalize_fingerprint(fingerprint: &str) -> String {
+    fingerprint
+        .chars()
+        .filter(|character| !character.is_whitespace())
+        .collect::<String>()
+        .to_uppercase()
+}
+
+/// The fingerprints of the primary keys in `gpg --show-keys --with-colons` output, skipping
+/// those of subkeys.
+pub fn parse_fingerprints(colons: &str) -> Vec<String> {
+    let mut fingerprints = Vec::new();
+    let mut after_primary_key = false;
+
+    for line in colons.lines() {
+        let fields: Vec<&str> = line.split(':').collect();
+
+        match fields[0] {
+            "pub" => after_primary_key = true,
+            "fpr" if after_primary_key => {
+                if let Some(fingerprint) = fields.get(9) {
+                    fingerprints.push(normalize_fingerprint(fingerprint));
+                }
+
+                after_primary_key = false;
+            }
+            _ => after_primary_key = false,
+        }
+    }
+
+    fingerprints
+}
+
+fn verify_fingerprints(
+    runner: &mut dyn Runner,
+    key_path: &Path,
+    key_url: &str,
+    fingerprints: &[String],
+) -> Result<(), Error> {
+    let mut expected: Vec<String> = fingerprints
+        .iter()
+        .map(|fingerprint| normalize_fingerprint(fingerprint))
+        .collect();
+
+    expected.sort();
+
+    // gpg --show-keys --with-colons /tmp/setup-coding-a1B2c3/docker.key
+    let command = CommandLine::new("gpg")
+        .args(["--show-keys", "--with-colons"])
+        .arg(key_path.to_string_lossy());
+
+    let output = match runner.verify(&command, &expected.join(" "))? {
+        None => return Ok(()),
+        Some(output) => output,
+    };
+
+    if !output.status.is_success() {
+        return Err(Error::NonZeroExit {
+            command: command.to_string(),
+            status: output.status,
+        });
+    }
+
+    let mut found = parse_fingerprints(&output.stdout);
+
+    found.sort();
+
+    if found != expected {
+        return Err(Error::FingerprintMismatch {
+            key_url: key_url.to_string(),
+            expected,
+            found,
+        });
+    }
+
+    progress!("verified fingerprints: {}", found.join(" "));
+
+    Ok(())
+}
+
+/// Downloads a repository signing key, checks that it holds exactly the pinned keys and
+/// installs it as a keyring of its own, so it is only trusted for that repository.
+///
+/// The key is downloaded into a private directory, so no one else can swap it between
+/// the check and the install.
+pub fn install_keyring(
+    runner: &mut dyn Runner,
+    name: &str,
+    key_url: &str,
+    key_armored: bool,
+    keyring: &str,
+    fingerprints: &[String],
+) -> Result<(), Error> {
+    let directory = runner.create_private_directory()?;
+    let key_path = directory.join(format!("{name}.key"));
+
+    let result = download_keyring(
+        runner,
+        &key_path,
+        key_url,
+        key_armored,
+        keyring,
+        fingerprints,
+    );
+
+    if let Err(error) = runner.remove_private_directory(&directory) {
+        verbose!("{error}");
+    }
+
+    result
+}
+
+fn download_keyring(
+    runner: &mut dyn Runner,
+    key_path: &Path,
+    key_url: &str,
+    key_armored: bool,
+    keyring: &str,
+    fingerprints: &[String],
+) -> Result<(), Error> {
+    let key_file = key_path.to_string_lossy().to_string();
+
+    // curl -fsSLo /tmp/setup-coding-a1B2c3/docker.key https://download.docker.com/linux/debian/gpg
+    run_step(
+        runner,
+        "downloaded signing key",
+        &[CommandLine::new("curl")
+            .args(["-fsSLo", &key_file])
+            .arg(key_url)],
+    )?;
+
+    verify_fingerprints(runner, key_path, key_url, fingerprints)?;
+
+    if let Some(directory) = Path::new(keyring).parent() {
+        // sudo mkdir -p /etc/apt/keyrings
+        run_step(
+            runner,
+            "created keyring directory",
+            &[CommandLine::new("sudo")
+                .args(["mkdir", "-p"])
+                .arg(directory.to_string_lossy())],
+        )?;
+    }
+
+    if key_armored {
+        // sudo gpg --dearmor --yes -o /usr/share/keyrings/docker-archive-keyring.gpg /tmp/setup-coding-a1B2c3/docker.key
+        run_step(
+            runner,
+            "installed keyring",
+            &[CommandLine::new("sudo").args([
+                "gpg",
+                "--dearmor",
+                "--yes",
+                "-o",
+                keyring,
+                &key_file,
+            ])],
+        )
+    } else {
+        // sudo install -m 644 /tmp/setup-coding-a1B2c3/gh.key /usr/share/keyrings/githubcli-archive-keyring.gpg
+        run_step(
+            runner,
+            "installed keyring",
+            &[CommandLine::new("sudo").args(["install", "-m", "644", &key_file, keyring])],
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runner::{RecordingRunner, ScriptedRunner};
+
+    const FINGERPRINT: &str = "9DC858229FC7DD38854AE2D88D81803C0EBFCD88";
+
+    fn install_docker_keyring(runner: &mut dyn Runner) -> Result<(), Error> {
+        install_keyring(
+            runner,
+            "docker",
+            "https://download.docker.com/linux/debian/gpg",
+            true,
+            "/etc/apt/keyrings/docker.gpg",
+            &[FINGERPRINT.to_string()],
+        )
+    }
+
+    #[test]
+    fn install_keyring_downloads_into_a_private_directory() {
+        let mut runner = RecordingRunner::new(ScriptedRunner::new().on_query(
+            "gpg --show-keys --with-colons /tmp/setup-coding-XXXXXX/docker.key",
+            &format!("pub:-:4096:1:8D81803C0EBFCD88:1487788586:::-:::scESA::::::23::0:\nfpr:::::::::{FINGERPRINT}:\n"),
+        ));
+
+        install_docker_keyring(&mut runner).unwrap();
+
+        assert_eq!(
+            runner.transcript(),
+            [
+                "create directory: /tmp/setup-coding-XXXXXX",
+                "run: curl -fsSLo /tmp/setup-coding-XXXXXX/docker.key https://download.docker.com/linux/debian/gpg",
+                "query: gpg --show-keys --with-colons /tmp/setup-coding-XXXXXX/docker.key",
+                "run: sudo mkdir -p /etc/apt/keyrings",
+                "run: sudo gpg --dearmor --yes -o /etc/apt/keyrings/docker.gpg /tmp/setup-coding-XXXXXX/docker.key",
+                "remove directory: /tmp/setup-coding-XXXXXX",
+            ]
+        );
+    }
+
+    #[test]
+    fn install_keyring_removes_the_directory_of_a_rejected_key() {
+        let mut runner = RecordingRunner::new(ScriptedRunner::new().on_query(
+            "gpg --show-keys --with-colons /tmp/setup-coding-XXXXXX/docker.key",
+            "pub:-:4096:1:0123456789ABCDEF:1487788586:::-:::scESA::::::23::0:\nfpr:::::::::0123456789ABCDEF0123456789ABCDEF01234567:\n",
+        ));
+
+        let error = install_docker_keyring(&mut runner).unwrap_err();
+
+        assert!(
+            matches!(error, Error::FingerprintMismatch { .. }),
+            "{error}"
+        );
+        assert_eq!(
+            runner.transcript().last().map(String::as_str),
+            Some("remove directory: /tmp/setup-coding-XXXXXX")
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use crate::config::Ssh;
+use crate::error::Error;
+use crate::progress;
+use crate::report::Outcome;
+use crate::run_step;
+use crate::runner::{CommandLine, Runner};
+use std::env;
+use std::path::{Path, PathBuf};
+
+/// The key types that can be generated with `ssh-keygen -t`, most preferred first.
+pub const SSH_ALGORITHMS: &[&str] = &["ed25519", "ecdsa", "rsa"];
+
+/// What the ssh key needs first: `gh`, to add it to github.
+pub const SSH_KEY_REQUIRES: &[&str] = &["gh"];
+
+pub fn get_home() -> Result<PathBuf, Error> {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| Error::MissingEnvironmentVariable("HOME".to_string()))
+}
+
+/// Where ssh-keygen puts a key of type `algorithm` for the user whose home is `home`.
+pub fn ssh_key_path_in(home: &Path, algorithm: &str) -> PathBuf {
+    home.join(".ssh").join(format!("id_{algorithm}"))
+}
+
+pub fn get_ssh_key_path(algorithm: &str) -> Result<PathBuf, Error> {
+    Ok(ssh_key_path_in(&get_home()?, algorithm))
+}
+
+pub fn generate_new_ssh_key(
+    runner: &mut dyn Runner,
+    ssh_key_path: &str,
+    algorithm: &str,
+    email: &str,
+    title: &str,
+) -> Result<(), Error> {
+    progress!("\ngenerating new ssh key");
+
+    // ssh-keygen -t ed25519 -C "your_email@example.com" -f ~/.ssh/id_ed25519
+    run_step(
+        runner,
+        "generated ssh key",
+        &[CommandLine::new("ssh-keygen").args(["-t", algorithm, "-C", email, "-f", ssh_key_path])],
+    )?;
+
+    // ssh-add ~/.ssh/id_ed25519
+    // the agent started by the desktop session is used, a child process cannot start one for it
+    run_step(
+        runner,
+        "added to the ssh agent",
+        &[CommandLine::new("ssh-add").arg(ssh_key_path)],
+    )?;
+
+    let ssh_public_key_path = format!("{ssh_key_path}.pub");
+
+    // gh ssh-key add ~/.ssh/id_ed25519.pub --title "personal laptop"
+    run_step(
+        runner,
+        "added ssh key to github",
+        &[CommandLine::new("gh").args(["ssh-key", "add", &ssh_public_key_path, "--title", title])],
+    )?;
+
+    Ok(())
+}
+
+pub fn target_ssh_key(runner: &mut dyn Runner, ssh: &Ssh) -> Outcome {
+    match get_ssh_key_path(&ssh.algorithm) {
+        Err(error) => Outcome::Failed(error.to_string()),
+        Ok(ssh_key_path) if ssh_key_path.exists() => {
+            Outcome::Skipped(format!("{} already exists", ssh_key_path.display()))
+        }
+        Ok(ssh_key_path) => match generate_new_ssh_key(
+            runner,
+            &ssh_key_path.to_string_lossy(),
+            &ssh.algorithm,
+            &ssh.email,
+            &ssh.title,
+        ) {
+            Ok(()) if runner.is_dry_run() => Outcome::Succeeded("dry run".to_string()),
+            Ok(()) => Outcome::Succeeded("generated".to_string()),
+            Err(error) => Outcome::Failed(error.to_string()),
+        },
+    }
+}
@@ -0,0 +1,251 @@
+pub mod check;
+pub mod checksum;
+pub mod condition;
+pub mod config;
+pub mod doctor;
+pub mod error;
+pub mod facts;
+pub mod graph;
+pub mod init;
+pub mod interpolation;
+pub mod keyring;
+pub mod keys;
+pub mod options;
+pub mod package_manager;
+pub mod recipe;
+pub mod registry;
+pub mod report;
+pub mod runner;
+pub mod schema;
+pub mod selection;
+pub mod sources;
+pub mod state_file;
+pub mod tools;
+pub mod updates;
+pub mod version;
+
+use config::TargetEnvironment;
+use error::Error;
+use facts::Facts;
+use graph::{Node, Prerequisites, Target};
+use registry::Registry;
+use report::{Outcome, Summary};
+use runner::{format_pipeline, CommandLine, Runner};
+use selection::{get_category_name, Selection};
+use state_file::StateFile;
+
+pub fn setup(
+    runner: &mut dyn Runner,
+    facts: &Facts,
+    target_environment: TargetEnvironment,
+    selection: &Selection,
+) -> Result<Summary, Error> {
+    let mut state_file = StateFile::load()?;
+    let summary = set_up_targets(
+        runner,
+        facts,
+        &mut state_file,
+        target_environment,
+        selection,
+    )?;
+
+    if !runner.is_dry_run() {
+        state_file.save()?;
+
+        progress!("\nsaved state: {}", state_file.path().display());
+    }
+
+    Ok(summary)
+}
+
+/// Acts on each entry of the plan in turn, recording what it installs in `state_file`.
+fn set_up_targets(
+    runner: &mut dyn Runner,
+    facts: &Facts,
+    state_file: &mut StateFile,
+    target_environment: TargetEnvironment,
+    selection: &Selection,
+) -> Result<Summary, Error> {
+    let mut registry = Registry::builtin()?;
+    let mut tools = target_environment.tools;
+
+    tools::add_custom_tools(&mut registry, &mut tools, target_environment.custom_tools)?;
+
+    let plan = graph::plan(
+        &registry,
+        facts,
+        selection,
+        target_environment.updates,
+        tools,
+        target_environment.keys,
+        Prerequisites::Include(runner),
+    )?;
+    let mut summary = Summary::new();
+
+    for excluded in plan.excluded {
+        summary.record(excluded.category, &excluded.name, excluded.outcome);
+    }
+
+    // a failed entry holds back the entries that require it, and those hold back theirs
+    let mut unsatisfied: Vec<(Node, &str)> = Vec::new();
+    let mut category = None;
+
+    for step in plan.steps {
+        if category != Some(step.node.category) {
+            progress!(
+                "\nchecking targets for {}",
+                get_category_name(step.node.category)
+            );
+
+            category = Some(step.node.category);
+        }
+
+        let name = &step.node.name;
+        let hold_back_reason = unsatisfied
+            .iter()
+            .find(|(node, _)| step.prerequisites.contains(node))
+            .map(|(node, what)| format!("requires {node}, which {what}"));
+
+        let outcome = match (hold_back_reason, step.target) {
+            (Some(reason), _) => {
+                unsatisfied.push((step.node.clone(), "was skipped"));
+
+                Outcome::Skipped(reason)
+            }
+            (None, Target::Update(enabled)) => updates::target_update(runner, name, enabled, facts),
+            (None, Target::Tool(tool_target)) => {
+                tools::target_tool(runner, &registry, facts, state_file, name, tool_target)
+                    .unwrap_or_else(|error| Outcome::Failed(error.to_string()))
+            }
+            (None, Target::Ssh(ssh)) => keys::target_ssh_key(runner, &ssh),
+        };
+
+        if let Outcome::Failed(_) = outcome {
+            unsatisfied.push((step.node.clone(), "failed"));
+        }
+
+        summary.record(step.node.category, name, outcome);
+    }
+
+    Ok(summary)
+}
+
+fn run_step(runner: &mut dyn Runner, message: &str, pipeline: &[CommandLine]) -> Result<(), Error> {
+    let status = runner.run(pipeline)?;
+
+    if runner.is_dry_run() {
+        return Ok(());
+    }
+
+    if !status.is_success() {
+        return Err(Error::NonZeroExit {
+            command: format_pipeline(pipeline),
+            status,
+        });
+    }
+
+    progress!("process status: {status}");
+    progress!("{message}");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use facts::DEBIAN;
+    use report::{Category, Entry};
+    use runner::{ScriptedRunner, Status};
+
+    /// Custom tools installed by a script: `b` requires `a`, and `c` requires `b`.
+    const CHAIN: &str = r#"
+[[custom_tools]]
+name = "a"
+detect = [{ command = ["a", "--version"] }]
+install = [{ type = "script", url = "https://example.com/a" }]
+
+[[custom_tools]]
+name = "b"
+requires = ["a"]
+detect = [{ command = ["b", "--version"] }]
+install = [{ type = "script", url = "https://example.com/b" }]
+
+[[custom_tools]]
+name = "c"
+requires = ["b"]
+detect = [{ command = ["c", "--version"] }]
+install = [{ type = "script", url = "https://example.com/c" }]
+"#;
+
+    fn set_up_chain(runner: &mut ScriptedRunner) -> Vec<Entry> {
+        let target_environment = toml::from_str(CHAIN).unwrap();
+        let summary = set_up_targets(
+            runner,
+            &Facts::fixture(DEBIAN),
+            &mut StateFile::empty(),
+            target_environment,
+            &Selection::default(),
+        )
+        .unwrap();
+
+        summary.entries().to_vec()
+    }
+
+    fn entry(name: &str, outcome: Outcome) -> Entry {
+        Entry {
+            category: Category::Tool,
+            name: name.to_string(),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn dependents_of_a_failed_prerequisite_are_skipped() {
+        let mut runner = ScriptedRunner::new().on_run(
+            "curl --proto =https --tlsv1.2 -fsSL https://example.com/a | sh",
+            Status::failure(1),
+        );
+
+        let entries = set_up_chain(&mut runner);
+
+        assert_eq!(
+            entries,
+            [
+                entry(
+                    "a",
+                    Outcome::Failed(
+                        "`curl --proto =https --tlsv1.2 -fsSL https://example.com/a | sh` failed with exit status: 1"
+                            .to_string()
+                    )
+                ),
+                entry(
+                    "b",
+                    Outcome::Skipped("requires tool a, which failed".to_string())
+                ),
+                entry(
+                    "c",
+                    Outcome::Skipped("requires tool b, which was skipped".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn dependents_of_an_installed_prerequisite_go_ahead() {
+        let mut runner = ScriptedRunner::new().on_query("a --version", "a 1.0.0\n");
+
+        let entries = set_up_chain(&mut runner);
+
+        assert_eq!(
+            entries,
+            [
+                entry(
+                    "a",
+                    Outcome::Skipped("already installed, not by setup-coding".to_string())
+                ),
+                entry("b", Outcome::Succeeded("installed".to_string())),
+                entry("c", Outcome::Succeeded("installed".to_string())),
+            ]
+        );
+    }
+}
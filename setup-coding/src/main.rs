@@ -1,1054 +1,585 @@
-use serde_derive::Deserialize;
-use std::env;
+use clap::{Args, Parser, Subcommand};
+use setup_coding::check::{check, Drift};
+use setup_coding::config::{
+    find_config_path, format_config, read_config_file, read_target_environment, Format,
+    TargetEnvironment,
+};
+use setup_coding::doctor::doctor;
+use setup_coding::error::Error;
+use setup_coding::facts::Facts;
+use setup_coding::init::init;
+use setup_coding::keys::get_home;
+use setup_coding::options::{self, Verbosity};
+use setup_coding::progress;
+use setup_coding::registry::Registry;
+use setup_coding::report::Summary;
+use setup_coding::runner::{DryRunRunner, Runner, SystemRunner};
+use setup_coding::schema::schema;
+use setup_coding::selection::Selection;
+use setup_coding::state_file::StateFile;
+use std::ffi::OsString;
 use std::fs;
-use std::io::Error;
-use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::process;
-use std::process::{Child, Command, Output, Stdio};
-use std::string::FromUtf8Error;
 
-#[derive(Debug, Deserialize)]
-struct DockerCompose {
-    version: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct TargetEnvironment {
-    keys: Option<Keys>,
-    tools: Option<Tools>,
-    updates: Option<Updates>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Keys {
-    ssh: Option<Ssh>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Node {
-    version: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct Ssh {
-    algorithm: String,
-    email: String,
-    title: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct Solana {
-    version: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct Tools {
-    brave_browser: Option<String>,
-    code: Option<String>,
-    codecov: Option<String>,
-    docker: Option<String>,
-    docker_compose: Option<DockerCompose>,
-    gh: Option<String>,
-    git: Option<String>,
-    google_chrome: Option<String>,
-    node: Option<Node>,
-    rustc: Option<String>,
-    solana: Option<Solana>,
-}
-
-#[derive(Debug, Deserialize)]
-struct Updates {
-    cleanup: Option<bool>,
-    dependencies: Option<bool>,
-    system: Option<bool>,
-}
-
-fn can_find_tool(tool_name: &str) -> bool {
-    println!("\nchecking for tool: {tool_name}");
-    let process = Command::new(tool_name).arg("--version").spawn();
-
-    let message = format!("found tool: {tool_name}");
-
-    check_process_status(&message, process)
-}
-
-fn can_find_version(tool_name: &str, version: &str) -> bool {
-    println!("\nchecking for tool and version: {tool_name} {version}");
-    let process = Command::new(tool_name)
-        .arg("--version")
-        .stdout(Stdio::piped())
-        .spawn();
-
-    let message = format!("found tool and version: {tool_name} {version}");
-
-    check_process_version(&message, process, version)
-}
-
-fn check_process_status(message: &str, process: Result<Child, Error>) -> bool {
-    match process {
-        Err(error) => {
-            println!("process error message: {error}");
-
-            false
-        }
-        Ok(mut child) => {
-            let exit_status = child.wait();
-
-            match exit_status {
-                Err(error) => {
-                    println!("exit status error message: {error}");
-
-                    false
-                }
-                Ok(status) => {
-                    println!("process status: {status}");
-                    println!("{message}");
-
-                    true
-                }
-            }
+/// Installs and configures the tools of a coding environment from a TOML, YAML or JSON file.
+///
+/// `setup-coding [--dry-run] FILE` from before there were subcommands still works, the same
+/// as `setup-coding apply FILE` or `setup-coding plan FILE`.
+#[derive(Debug, Parser)]
+#[command(version, allow_external_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Print the commands instead of running them, the same as `plan`
+    #[arg(long, global = true, hide = true)]
+    dry_run: bool,
+
+    #[command(flatten)]
+    global: GlobalArgs,
+}
+
+#[derive(Debug, Args)]
+struct GlobalArgs {
+    /// Config file, defaults to ./setup-coding.toml or $XDG_CONFIG_HOME/setup-coding/config.toml
+    #[arg(long, short, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Format of the config file, detected from its extension when not given: toml, yaml or json
+    #[arg(long, global = true, value_name = "FORMAT")]
+    format: Option<Format>,
+
+    /// Only act on these entries or categories (tools, updates, keys), comma separated
+    #[arg(long, global = true, value_delimiter = ',', value_name = "NAMES")]
+    only: Vec<String>,
+
+    /// Leave these entries or categories alone, comma separated
+    #[arg(long, global = true, value_delimiter = ',', value_name = "NAMES")]
+    skip: Vec<String>,
+
+    /// Let package managers go ahead without asking for confirmation
+    #[arg(long, short, global = true)]
+    yes: bool,
+
+    /// Also print every command and query as it runs
+    #[arg(long, short, global = true, conflicts_with = "quiet")]
+    verbose: bool,
+
+    /// Only print errors and the result
+    #[arg(long, short, global = true)]
+    quiet: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Install, update and remove until the system matches the config
+    Apply {
+        #[arg(value_name = "FILE")]
+        file: Option<PathBuf>,
+    },
+    /// Print what apply would do without changing anything
+    Plan {
+        #[arg(value_name = "FILE")]
+        file: Option<PathBuf>,
+    },
+    /// Report how the system differs from the config as JSON, exiting 1 when it does
+    Check {
+        #[arg(value_name = "FILE")]
+        file: Option<PathBuf>,
+    },
+    /// Print a config that reproduces the tools, versions and ssh key of this machine
+    Init,
+    /// Check that this system has what setup-coding needs
+    Doctor,
+    /// List the tools that can be installed, and the ones setup-coding installed
+    ListTools,
+    /// Print the JSON Schema of the config format, e.g. for `#:schema` in taplo
+    Schema,
+    /// Print the config after merging its includes and the overlay for this host
+    ShowConfig {
+        #[arg(value_name = "FILE")]
+        file: Option<PathBuf>,
+    },
+    /// Translate a config file between TOML, YAML and JSON, keeping its variables and includes
+    Convert {
+        #[arg(value_name = "FILE")]
+        file: Option<PathBuf>,
+
+        /// Where to write the converted config, instead of stdout
+        #[arg(value_name = "OUTPUT")]
+        output: Option<PathBuf>,
+
+        /// Format to convert to, detected from the extension of OUTPUT when not given
+        #[arg(long, value_name = "FORMAT", required_unless_present = "output")]
+        to: Option<Format>,
+    },
+    /// A config file in place of a subcommand, as in `setup-coding FILE`
+    #[command(external_subcommand)]
+    File(Vec<OsString>),
+}
+
+/// Parses the command line, reading `setup-coding FILE ...` as `setup-coding apply FILE ...`.
+///
+/// The file is taken as an unknown subcommand, so that flags before it are not mistaken for
+/// options of a positional argument, then everything is parsed again with `apply` in front
+/// of it, so that flags after it count too.
+fn parse_cli(args: Vec<OsString>) -> Result<Cli, clap::Error> {
+    let cli = Cli::try_parse_from(&args)?;
+
+    let position = match &cli.command {
+        Some(Command::File(rest)) => args.len() - rest.len(),
+        _ => return Ok(cli),
+    };
+
+    let mut args = args;
+
+    args.insert(position, OsString::from("apply"));
+
+    Cli::try_parse_from(args)
+}
+
+impl GlobalArgs {
+    fn selection(&self) -> Selection {
+        Selection {
+            only: self.only.clone(),
+            skip: self.skip.clone(),
         }
     }
-}
 
-fn check_process_version(message: &str, process: Result<Child, Error>, version: &str) -> bool {
-    match process {
-        Err(error) => {
-            println!("process error message: {error}");
-
-            false
+    /// The config named on the command line, or else the first default one that exists.
+    fn config_path(&self, file: Option<PathBuf>) -> Result<PathBuf, Error> {
+        match file.or_else(|| self.config.clone()) {
+            None => find_config_path(),
+            Some(path) => Ok(path),
         }
-        Ok(mut child) => match child.wait() {
-            Ok(status) => match child.stdout.take() {
-                Some(ref mut stdout) => {
-                    let mut buf_string = String::new();
-                    match stdout.read_to_string(&mut buf_string) {
-                        Ok(_) => {
-                            println!("read string: {buf_string}");
-                            println!("process status: {status}");
-
-                            if buf_string.contains(version) {
-                                println!("{message}");
-
-                                true
-                            } else {
-                                println!("not {message}");
-
-                                false
-                            }
-                        }
-                        Err(_) => {
-                            println!("Could not read string");
-
-                            false
-                        }
-                    }
-                }
-                None => {
-                    println!("No output");
-
-                    false
-                }
-            },
-            Err(_) => {
-                println!("process failed");
-
-                false
-            }
-        },
     }
-}
 
-fn convert_output_to_string(process_child_stdout: Output) -> Result<String, FromUtf8Error> {
-    let architecture_name_result = String::from_utf8(process_child_stdout.stdout);
+    /// The format given with --format, or else the one of the extension of `path`.
+    fn format(&self, path: &Path) -> Format {
+        self.format.unwrap_or_else(|| Format::from_path(path))
+    }
 
-    match architecture_name_result {
-        Err(error) => {
-            println!("error when trying to convert string from dpkg output: {error}");
+    fn read_config(
+        &self,
+        facts: &Facts,
+        file: Option<PathBuf>,
+    ) -> Result<TargetEnvironment, Error> {
+        let path = self.config_path(file)?;
 
-            Err(error)
-        }
-        Ok(architecture_name) => Ok(architecture_name.trim().to_string()),
+        read_target_environment(&path, self.format(&path), facts)
     }
 }
 
-fn generate_new_ssh_key(algorithm: &str, email: &str, title: &str) {
-    println!("\ngenerating new ssh key");
-
-    // ssh-keygen -t ed25519 -C "your_email@example.com"
-    let ssh_keygen_process = Command::new("ssh-keygen")
-        .arg("-t")
-        .arg(&algorithm)
-        .arg("-C")
-        .arg(email)
-        .spawn();
-
-    check_process_status("generated ssh key", ssh_keygen_process);
-
-    // eval "$(ssh-agent -s)"
-    let eval_process = Command::new("eval").arg("$(ssh-agent -s)").spawn();
-
-    check_process_status("started the ssh agent", eval_process);
+fn run(global: &GlobalArgs, file: Option<PathBuf>, dry_run: bool) -> Result<Summary, Error> {
+    let facts = Facts::detect();
+    let target_environment = global.read_config(&facts, file)?;
 
-    let ssh_directory = format!("~/.ssh/{algorithm}");
+    let mut system_runner = SystemRunner;
+    let mut dry_run_runner = DryRunRunner::new(SystemRunner);
 
-    // sh-add ~/.ssh/id_ed25519
-    let ssh_add_process = Command::new("ssh-add").arg(&ssh_directory).spawn();
+    let runner: &mut dyn Runner = if dry_run {
+        progress!("\ndry run: commands that change the system are printed, not run");
 
-    check_process_status("added to the ssh agent", ssh_add_process);
+        &mut dry_run_runner
+    } else {
+        &mut system_runner
+    };
 
-    // gh ssh-key add ~/.ssh/id_ed25519.pub --title "personal laptop"
-    let gh_process = Command::new("gh")
-        .arg("ssh-key")
-        .arg("add")
-        .arg(ssh_directory)
-        .arg("--title")
-        .arg(title)
-        .spawn();
-
-    check_process_status("added ssh key to github", gh_process);
+    setup_coding::setup(runner, &facts, target_environment, &global.selection())
 }
 
-fn get_architecture_name_output() -> Result<Output, Error> {
-    println!("\ngetting architecture name");
-
-    // dpkg --print-architecture
-    let dpkg_process_child_result = Command::new("dpkg")
-        .arg("--print-architecture")
-        .stdout(Stdio::piped())
-        .spawn();
-
-    match dpkg_process_child_result {
-        Err(error) => {
-            println!("error when trying to dpkg: {error}");
-
-            Err(error)
-        }
-        Ok(dpkg_process_child) => {
-            let dpkg_process_child_stdout_result = dpkg_process_child.wait_with_output();
-
-            match dpkg_process_child_stdout_result {
-                Err(error) => {
-                    println!("error when trying to dpkg: {error}");
+fn run_check(global: &GlobalArgs, file: Option<PathBuf>) -> Result<Vec<Drift>, Error> {
+    let facts = Facts::detect();
+    let target_environment = global.read_config(&facts, file)?;
 
-                    Err(error)
-                }
-                Ok(dpkg_process_child_stdout) => Ok(dpkg_process_child_stdout),
-            }
-        }
-    }
+    // a dry run runner, so that nothing can change the system even by mistake
+    check(
+        &mut DryRunRunner::new(SystemRunner),
+        &facts,
+        &StateFile::load()?,
+        target_environment,
+        &global.selection(),
+    )
 }
 
-fn get_kernel_name_output() -> Result<Output, Error> {
-    println!("\ngetting kernel name");
-
-    // uname -s
-    let uname_process_child_result = Command::new("uname")
-        .arg("-s")
-        .stdout(Stdio::piped())
-        .spawn();
-
-    match uname_process_child_result {
-        Err(error) => {
-            println!("error when trying to uname: {error}");
+fn run_init() -> Result<String, Error> {
+    let facts = Facts::detect();
 
-            Err(error)
-        }
-        Ok(uname_process_child) => {
-            let uname_process_child_stdout_result = uname_process_child.wait_with_output();
-
-            match uname_process_child_stdout_result {
-                Err(error) => {
-                    println!("error when trying to get output of uname: {error}");
+    // a dry run runner, like check, since init only looks at the system
+    let target_environment = init(&mut DryRunRunner::new(SystemRunner), &facts, &get_home()?)?;
 
-                    Err(error)
-                }
-                Ok(uname_process_child_stdout_result) => Ok(uname_process_child_stdout_result),
-            }
-        }
-    }
+    format_config(&target_environment, Format::Toml)
 }
 
-fn get_machine_hardware_name_output() -> Result<Output, Error> {
-    println!("\ngetting machine hardware name");
+fn show_config(global: &GlobalArgs, file: Option<PathBuf>) -> Result<String, Error> {
+    let facts = Facts::detect();
 
-    // uname -s
-    let uname_process_child_result = Command::new("uname")
-        .arg("-m")
-        .stdout(Stdio::piped())
-        .spawn();
-
-    match uname_process_child_result {
-        Err(error) => {
-            println!("error when trying to uname: {error}");
-
-            Err(error)
-        }
-        Ok(uname_process_child) => {
-            let uname_process_child_stdout_result = uname_process_child.wait_with_output();
-
-            match uname_process_child_stdout_result {
-                Err(error) => {
-                    println!("error when trying to get output of uname: {error}");
-
-                    Err(error)
-                }
-                Ok(uname_process_child_stdout_result) => Ok(uname_process_child_stdout_result),
-            }
-        }
-    }
+    format_config(&global.read_config(&facts, file)?, Format::Toml)
 }
 
-fn get_release_name_output() -> Result<Output, Error> {
-    println!("\ngetting release name");
+/// Writes a config in another format, or returns it to print when there is no `output`.
+fn convert(
+    global: &GlobalArgs,
+    file: Option<PathBuf>,
+    output: Option<PathBuf>,
+    to: Option<Format>,
+) -> Result<Option<String>, Error> {
+    let path = global.config_path(file)?;
+    let target_environment = read_config_file(&path, global.format(&path))?;
 
-    // lsb_release -cs
-    let lsb_release_process_child_result = Command::new("lsb_release")
-        .arg("-cs")
-        .stdout(Stdio::piped())
-        .spawn();
+    let format = match (to, &output) {
+        (Some(format), _) => format,
+        (None, Some(output)) => Format::from_path(output),
+        (None, None) => unreachable!("clap requires --to without an output file"),
+    };
+    let config = format_config(&target_environment, format)?;
 
-    match lsb_release_process_child_result {
-        Err(error) => {
-            println!("error when trying to lsb_release: {error}");
+    match output {
+        None => Ok(Some(config)),
+        Some(output) => {
+            fs::write(&output, config).map_err(|source| Error::WriteFile {
+                path: output.clone(),
+                source,
+            })?;
 
-            Err(error)
-        }
-        Ok(lsb_release_process_child) => {
-            let lsb_release_process_child_stdout_result =
-                lsb_release_process_child.wait_with_output();
-
-            match lsb_release_process_child_stdout_result {
-                Err(error) => {
-                    println!("error when trying to get output of lsb_release: {error}");
+            progress!("wrote {format} config: {}", output.display());
 
-                    Err(error)
-                }
-                Ok(lsb_release_process_child_stdout) => Ok(lsb_release_process_child_stdout),
-            }
+            Ok(None)
         }
     }
 }
 
-fn install_brave_browser() {
-    println!("\ninstalling tool: brave-browser");
-
-    // sudo curl -fsSLo /usr/share/keyrings/brave-browser-archive-keyring.gpg https://brave-browser-apt-release.s3.brave.com/brave-browser-archive-keyring.gpg
-    let apt_curl_process = Command::new("sudo")
-        .arg("curl")
-        .arg("-fsSLo")
-        .arg("/usr/share/keyrings/brave-browser-archive-keyring.gpg")
-        .arg("https://brave-browser-apt-release.s3.brave.com/brave-browser-archive-keyring.gpg")
-        .spawn();
-
-    check_process_status("downloaded gpg file", apt_curl_process);
+/// One line per tool: its name, install methods and what the state file records about it.
+fn list_tools(global: &GlobalArgs) -> Result<String, Error> {
+    let mut registry = Registry::builtin()?;
+    let state_file = StateFile::load()?;
 
-    // echo "deb [signed-by=/usr/share/keyrings/brave-browser-archive-keyring.gpg arch=amd64] https://brave-browser-apt-release.s3.brave.com/ stable main"
-    let echo_process_child_result = Command::new("echo")
-        .arg("deb [signed-by=/usr/share/keyrings/brave-browser-archive-keyring.gpg arch=amd64] https://brave-browser-apt-release.s3.brave.com/ stable main")
-        .stdout(Stdio::piped())
-        .spawn();
+    // custom tools are listed too when there is a config to read them from
+    if let Ok(path) = global.config_path(None) {
+        let target_environment = global.read_config(&Facts::detect(), Some(path))?;
 
-    match echo_process_child_result {
-        Err(error) => {
-            println!("could not install brave-browser: {error}");
-        }
-        Ok(mut echo_process_child) => {
-            if let Some(echo_process) = echo_process_child.stdout.take() {
-                // |sudo tee /etc/apt/sources.list.d/brave-browser-release.list
-                let tee_process = Command::new("sudo")
-                    .arg("tee")
-                    .arg("/etc/apt/sources.list.d/brave-browser-release.list")
-                    .stdin(echo_process)
-                    .spawn();
-
-                check_process_status("writing to sources file", tee_process);
-
-                // sudo apt update
-                let apt_update_process = Command::new("sudo").arg("apt").arg("update").spawn();
-
-                check_process_status("updated apt", apt_update_process);
-
-                // sudo apt install brave-browser
-                let apt_install_process = Command::new("sudo")
-                    .arg("apt")
-                    .arg("install")
-                    .arg("brave-browser")
-                    .spawn();
-
-                check_process_status("installed tool: brave-browser", apt_install_process);
-            }
+        for recipe in target_environment.custom_tools.unwrap_or_default() {
+            registry.add(recipe)?;
         }
     }
-}
 
-fn install_code() {
-    println!("\ninstalling tool: code");
-
-    // sudo snap install code --classic
-    let process = Command::new("sudo")
-        .arg("snap")
-        .arg("install")
-        .arg("code")
-        .arg("--classic")
-        .spawn();
+    let name_width = registry
+        .recipes()
+        .iter()
+        .map(|recipe| recipe.name.len())
+        .max()
+        .unwrap_or(0);
+    let mut lines = Vec::new();
+
+    for recipe in registry.recipes() {
+        let methods = recipe
+            .install
+            .iter()
+            .map(|method| method.label())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let recorded = match state_file.get(&recipe.name) {
+            None => String::new(),
+            Some(record) => format!(
+                "installed {}with {} at {}",
+                record
+                    .version
+                    .as_ref()
+                    .map(|version| format!("{version} "))
+                    .unwrap_or_default(),
+                record.method,
+                record.installed_at
+            ),
+        };
+
+        let line = format!("{:<name_width$}  {methods}  {recorded}", recipe.name);
+
+        lines.push(line.trim_end().to_string());
+    }
 
-    check_process_status("installed tool: code", process);
+    Ok(lines.join("\n"))
 }
 
-fn install_codecov() {
-    println!("\ninstalling tool: codecov");
-
-    // curl -Os https://uploader.codecov.io/latest/linux/codecov
-    let curl_process = Command::new("curl")
-        .arg("-Os")
-        .arg("https://uploader.codecov.io/latest/linux/codecov")
-        .arg("-o")
-        .arg("/usr/local/bin/codecov")
-        .spawn();
-
-    check_process_status("downloaded codecov", curl_process);
-
-    // chmod +x codecov
-    let chmod_process = Command::new("chmod").arg("+x").arg("codecov").spawn();
-
-    check_process_status("made codecov executable by current user", chmod_process);
+fn exit_with_error(error: Error) -> ! {
+    eprintln!("\nerror: {error}");
 
-    // mv codecov /usr/local/bin/codecov
-    let mv_process = Command::new("sudo")
-        .arg("mv")
-        .arg("codecov")
-        .arg("/usr/local/bin/codecov")
-        .spawn();
-
-    check_process_status("installed tool: codecov", mv_process);
+    process::exit(1);
 }
 
-fn install_docker(architecture_name: &str, release_name: &str) {
-    println!("\ninstalling tool: docker");
-
-    // curl -fsSL https://download.docker.com/linux/ubuntu/gpg
-    let curl_process_child_result = Command::new("curl")
-        .arg("-fsSL")
-        .arg("https://download.docker.com/linux/ubuntu/gpg")
-        .stdout(Stdio::piped())
-        .spawn();
-
-    match curl_process_child_result {
-        Err(error) => {
-            println!("error when trying to download: {error}");
-        }
-        Ok(mut curl_process_child) => {
-            if let Some(curl_process) = curl_process_child.stdout.take() {
-                // | sudo gpg --dearmor -o /usr/share/keyrings/docker-archive-keyring.gpg
-                let gpg_process = Command::new("sudo")
-                    .arg("gpg")
-                    .arg("--dearmor")
-                    .arg("-o")
-                    .arg("/usr/share/keyrings/docker-archive-keyring.gpg")
-                    .stdin(curl_process)
-                    .spawn();
-
-                check_process_status("downloaded gpg file", gpg_process);
-
-                let echo_argument = format!("deb [arch={} signed-by=/usr/share/keyrings/docker-archive-keyring.gpg] https://download.docker.com/linux/ubuntu {} stable", architecture_name, release_name);
-
-                // echo "deb [arch=$(dpkg --print-architecture) signed-by=/usr/share/keyrings/docker-archive-keyring.gpg] https://download.docker.com/linux/ubuntu $(lsb_release -cs) stable"
-                let echo_process_child_result = Command::new("echo")
-                    .arg(echo_argument)
-                    .stdout(Stdio::piped())
-                    .spawn();
-
-                match echo_process_child_result {
-                    Err(error) => {
-                        println!("error when processing echo: {error}");
-                    }
-                    Ok(mut echo_process_child) => {
-                        if let Some(echo_process) = echo_process_child.stdout.take() {
-                            // | sudo tee /etc/apt/sources.list.d/docker.list > /dev/null
-                            let tee_process = Command::new("sudo")
-                                .arg("tee")
-                                .arg("/etc/apt/sources.list.d/docker.list")
-                                .stdin(echo_process)
-                                .spawn();
-
-                            check_process_status("writing to sources file", tee_process);
-
-                            // sudo apt-get install docker-ce docker-ce-cli containerd.io
-                            let apt_install_process = Command::new("sudo")
-                                .arg("apt-get")
-                                .arg("install")
-                                .arg("docker-ce")
-                                .arg("docker-ce-cli")
-                                .arg("containerd.io")
-                                .spawn();
-
-                            check_process_status("installed tool: docker", apt_install_process);
-                        }
-                    }
+fn main() {
+    // end quietly when the output is piped into something that stops reading it, like
+    // `setup-coding plan | head`, as other command line tools do, instead of failing writes
+    // SAFETY: nothing else runs yet that could be handling signals
+    unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+
+    let cli = parse_cli(std::env::args_os().collect()).unwrap_or_else(|error| error.exit());
+    let global = &cli.global;
+
+    options::set_assume_yes(global.yes);
+    options::set_verbosity(match (global.quiet, global.verbose) {
+        (true, _) => Verbosity::Quiet,
+        (_, true) => Verbosity::Verbose,
+        _ => Verbosity::Normal,
+    });
+
+    // `--dry-run` is from before there were subcommands, when applying was all there was
+    let command = match cli.command {
+        Some(Command::Apply { file }) if cli.dry_run => Command::Plan { file },
+        Some(command) => command,
+        None if cli.dry_run => Command::Plan { file: None },
+        None => Command::Apply { file: None },
+    };
+
+    match command {
+        Command::Apply { file } => print_summary(run(global, file, false)),
+        Command::Plan { file } => print_summary(run(global, file, true)),
+        Command::Check { file } => match run_check(global, file) {
+            Err(error) => exit_with_error(error),
+            Ok(drifts) => {
+                let json = serde_json::to_string_pretty(&drifts)
+                    .expect("drifts only hold strings and enums");
+
+                println!("{json}");
+
+                if !drifts.is_empty() {
+                    eprintln!("\n{} entries are not satisfied", drifts.len());
+
+                    process::exit(1);
                 }
             }
-        }
-    }
-}
-
-fn install_docker_compose(_version: &str, kernel_name: &str, machine_hardware_name: &str) {
-    println!("\ninstalling tool: docker-compose");
-
-    let github_url = format!(
-        "https://github.com/docker/compose/releases/download/1.29.2/docker-compose-{kernel_name}-{machine_hardware_name}",
-    );
-
-    // sudo curl -L "https://github.com/docker/compose/releases/download/1.29.2/docker-compose-$(uname -s)-$(uname -m)" -o /usr/local/bin/docker-compose
-    let curl_process = Command::new("sudo")
-        .arg("curl")
-        .arg("-L")
-        .arg(github_url)
-        .arg("-o")
-        .arg("/usr/local/bin/docker-compose")
-        .spawn();
-
-    check_process_status("downloaded docker-compose from github", curl_process);
-
-    // sudo chmod +x /usr/local/bin/docker-compose
-    let chmod_process = Command::new("sudo")
-        .arg("chmod")
-        .arg("+x")
-        .arg("/usr/local/bin/docker-compose")
-        .spawn();
-    check_process_status("chmoded downloaded file", chmod_process);
-
-    // sudo ln -s /usr/local/bin/docker-compose /usr/bin/docker-compose
-    let ln_process = Command::new("sudo")
-        .arg("ln")
-        .arg("-s")
-        .arg("/usr/local/bin/docker-compose")
-        .arg("/usr/bin/docker-compose")
-        .spawn();
-
-    check_process_status("installed tool: docker-compose", ln_process);
-}
-
-fn install_gh(architecture_name: &str) {
-    println!("\ninstalling tool: gh");
+        },
+        Command::Init => match run_init() {
+            Err(error) => exit_with_error(error),
+            Ok(config) => print!("{config}"),
+        },
+        Command::Doctor => {
+            let report = doctor(&Facts::detect(), global.config_path(None));
 
-    // curl -fsSL https://cli.github.com/packages/githubcli-archive-keyring.gpg
-    let curl_process_child_result = Command::new("curl")
-        .arg("-fsSL")
-        .arg("https://cli.github.com/packages/githubcli-archive-keyring.gpg")
-        .stdout(Stdio::piped())
-        .spawn();
+            print!("{report}");
 
-    match curl_process_child_result {
-        Err(error) => {
-            println!("error when trying to curl: {error}");
-        }
-        Ok(mut curl_process_child) => {
-            if let Some(curl_process) = curl_process_child.stdout.take() {
-                // | sudo dd of=/usr/share/keyrings/githubcli-archive-keyring.gpg
-                let dd_process = Command::new("sudo")
-                    .arg("dd")
-                    .arg("of=/usr/share/keyrings/githubcli-archive-keyring.gpg")
-                    .stdin(curl_process)
-                    .spawn();
-
-                check_process_status("downloading gpg file", dd_process);
-
-                let echo_argument = format!("deb [arch={architecture_name} signed-by=/usr/share/keyrings/githubcli-archive-keyring.gpg] https://cli.github.com/packages stable main");
-
-                // echo "deb [arch=$(dpkg --print-architecture) signed-by=/usr/share/keyrings/githubcli-archive-keyring.gpg] https://cli.github.com/packages stable main"
-                let echo_process_child_result = Command::new("echo")
-                    .arg(echo_argument)
-                    .stdout(Stdio::piped())
-                    .spawn();
-
-                match echo_process_child_result {
-                    Err(error) => {
-                        println!("error when trying to echo: {error}");
-                    }
-                    Ok(mut echo_process_child) => {
-                        if let Some(echo_process) = echo_process_child.stdout.take() {
-                            // | sudo tee /etc/apt/sources.list.d/github-cli.list
-                            let tee_process = Command::new("sudo")
-                                .arg("tee")
-                                .arg("/etc/apt/sources.list.d/github-cli.list")
-                                .stdin(echo_process)
-                                .spawn();
-
-                            check_process_status("creating repository source file", tee_process);
-
-                            // sudo apt update
-                            let apt_update_process =
-                                Command::new("sudo").arg("apt").arg("update").spawn();
-
-                            check_process_status("updating system", apt_update_process);
-
-                            // sudo apt install gh
-                            let apt_install_process = Command::new("sudo")
-                                .arg("apt")
-                                .arg("install")
-                                .arg("gh")
-                                .spawn();
-
-                            check_process_status("installed tool: gh", apt_install_process);
-
-                            // gh auth login
-                            let gh_process = Command::new("gh").arg("auth").arg("login").spawn();
-
-                            check_process_status("logged into gh", gh_process);
-                        }
-                    }
-                }
+            if report.has_problems() {
+                process::exit(1);
             }
         }
+        Command::ListTools => match list_tools(global) {
+            Err(error) => exit_with_error(error),
+            Ok(tools) => println!("{tools}"),
+        },
+        Command::ShowConfig { file } => match show_config(global, file) {
+            Err(error) => exit_with_error(error),
+            Ok(config) => print!("{config}"),
+        },
+        Command::Convert { file, output, to } => match convert(global, file, output, to) {
+            Err(error) => exit_with_error(error),
+            Ok(config) => print!("{}", config.unwrap_or_default()),
+        },
+        Command::File(_) => unreachable!("parse_cli turns a file into `apply FILE`"),
+        Command::Schema => match schema() {
+            Err(error) => exit_with_error(error),
+            Ok(schema) => {
+                let json = serde_json::to_string_pretty(&schema)
+                    .expect("a schema is already a JSON value");
+
+                println!("{json}");
+            }
+        },
     }
 }
 
-fn install_git() {
-    println!("\ninstalling tool: git");
-
-    // sudo apt install git-all
-    let process = Command::new("sudo")
-        .arg("apt")
-        .arg("install")
-        .arg("git-all")
-        .spawn();
-
-    check_process_status("installed tool: git", process);
-}
+fn print_summary(result: Result<Summary, Error>) {
+    match result {
+        Err(error) => exit_with_error(error),
+        Ok(summary) => {
+            println!("\nsummary\n{summary}");
 
-fn install_google_chrome() {
-    println!("\ninstalling tool: google-chrome");
-
-    // wget -q -O - https://dl.google.com/linux/linux_signing_key.pub
-    let wget_process_child_result = Command::new("wget")
-        .arg("-q")
-        .arg("-O")
-        .arg("-")
-        .arg("https://dl.google.com/linux/linux_signing_key.pub")
-        .stdout(Stdio::piped())
-        .spawn();
-
-    match wget_process_child_result {
-        Err(error) => {
-            println!("error when trying to download: {error}");
-        }
-        Ok(mut wget_process_child) => {
-            if let Some(wget_process) = wget_process_child.stdout.take() {
-                // | sudo apt-key add -
-                let apt_key_process = Command::new("sudo")
-                    .arg("apt-key")
-                    .arg("add")
-                    .arg("-")
-                    .stdin(wget_process)
-                    .spawn();
-
-                check_process_status("downloaded linux signing key", apt_key_process);
-
-                // echo "deb [arch=amd64] http://dl.google.com/linux/chrome/deb/ stable main"
-                let echo_process_child_result = Command::new("echo")
-                    .arg("deb [arch=amd64] http://dl.google.com/linux/chrome/deb/ stable main")
-                    .stdout(Stdio::piped())
-                    .spawn();
-
-                match echo_process_child_result {
-                    Err(error) => {
-                        println!("error when trying to echo: {error}");
-                    }
-                    Ok(mut echo_process_child) => {
-                        if let Some(echo_process) = echo_process_child.stdout.take() {
-                            // | sudo tee /etc/apt/sources.list.d/google-chrome.list
-                            let tee_process = Command::new("sudo")
-                                .arg("tee")
-                                .arg("/etc/apt/sources.list.d/google-chrome.list")
-                                .stdin(echo_process)
-                                .spawn();
-
-                            check_process_status("creating repository source file", tee_process);
-
-                            // sudo apt update
-                            let apt_install_process =
-                                Command::new("sudo").arg("apt").arg("update").spawn();
-
-                            check_process_status("updated apt", apt_install_process);
-
-                            // sudo apt install google-chrome-stable
-                            let apt_install_process = Command::new("sudo")
-                                .arg("apt")
-                                .arg("install")
-                                .arg("google-chrome-stable")
-                                .spawn();
-
-                            check_process_status(
-                                "installed tool: google-chrome",
-                                apt_install_process,
-                            );
-                        }
-                    }
-                }
+            if summary.has_failures() {
+                process::exit(1);
             }
         }
     }
 }
 
-fn install_node(version: &str) {
-    println!("\ninstalling tool: node");
-
-    let package_url = format!("https://deb.nodesource.com/setup_{version}.x");
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // curl -fsSL https://deb.nodesource.com/setup_17.x
-    let curl_process_child_result = Command::new("curl")
-        .arg("-fsSL")
-        .arg(&package_url)
-        .stdout(Stdio::piped())
-        .spawn();
+    fn parse(line: &str) -> Cli {
+        let args = ["setup-coding"]
+            .into_iter()
+            .chain(line.split_whitespace())
+            .map(OsString::from)
+            .collect();
 
-    match curl_process_child_result {
-        Err(error) => {
-            println!("error trying to curl: {error}");
-        }
-        Ok(mut curl_process_child) => {
-            if let Some(curl_process) = curl_process_child.stdout.take() {
-                // | sudo -E bash -
-                let bash_process = Command::new("sudo")
-                    .arg("-E")
-                    .arg("bash")
-                    .arg("-")
-                    .stdin(curl_process)
-                    .spawn();
-
-                check_process_status("setup node in repository", bash_process);
-
-                // sudo apt-get install -y nodejs
-                let apt_get_process = Command::new("sudo")
-                    .arg("apt-get")
-                    .arg("install")
-                    .arg("-y")
-                    .arg("nodejs")
-                    .spawn();
-
-                check_process_status("installed tool: node", apt_get_process);
-            }
-        }
+        parse_cli(args).unwrap_or_else(|error| panic!("`{line}` does not parse: {error}"))
     }
-}
-
-fn install_rustc() {
-    println!("\ninstalling tool: rustc");
-
-    // curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs
-    let curl_process_child_result = Command::new("curl")
-        .arg("--proto")
-        .arg("=https")
-        .arg("--tlsv1.2")
-        .arg("-sSf")
-        .arg("https://sh.rustup.rs")
-        .stdout(Stdio::piped())
-        .spawn();
-
-    match curl_process_child_result {
-        Err(error) => {
-            println!("error trying to curl: {error}");
-        }
-        Ok(mut curl_process_child) => {
-            if let Some(curl_process) = curl_process_child.stdout.take() {
-                // | sh
-                let sh_process = Command::new("sh").stdin(curl_process).spawn();
 
-                check_process_status("installed tool: rustc", sh_process);
-            }
-        }
+    fn assert_globals(cli: &Cli, line: &str) {
+        assert_eq!(
+            cli.global.config,
+            Some(PathBuf::from("team.toml")),
+            "{line}"
+        );
+        assert_eq!(cli.global.only, ["tools", "keys"], "{line}");
+        assert!(cli.global.quiet, "{line}");
+        assert!(cli.global.yes, "{line}");
     }
-}
 
-fn install_solana(version: &str) {
-    println!("\ninstalling tool: solana");
-
-    let curl_url = format!("https://release.solana.com/v{version}/install");
-
-    // curl -sSfL https://release.solana.com/v1.9.7/install
-    let curl_process_child_result = Command::new("curl")
-        .arg("-sSfL")
-        .arg(&curl_url)
-        .stdout(Stdio::piped())
-        .spawn();
-
-    match curl_process_child_result {
-        Err(error) => {
-            println!("error trying to curl: {error}");
-        }
-        Ok(mut curl_process_child) => {
-            if let Some(curl_process) = curl_process_child.stdout.take() {
-                // | sh -c
-                let sh_process = Command::new("sh").stdin(curl_process).spawn();
-
-                check_process_status("installed tool: rustc", sh_process);
-            }
+    const SUBCOMMANDS: &[&str] = &[
+        "apply",
+        "plan",
+        "check",
+        "init",
+        "doctor",
+        "list-tools",
+        "schema",
+        "show-config",
+        "convert --to yaml",
+    ];
+
+    const GLOBALS: &str = "--config team.toml -q --only tools,keys -y";
+
+    #[test]
+    fn global_flags_before_a_subcommand() {
+        for subcommand in SUBCOMMANDS {
+            let line = format!("{GLOBALS} {subcommand}");
+            let cli = parse(&line);
+
+            assert!(cli.command.is_some(), "{line}");
+            assert!(!matches!(cli.command, Some(Command::File(_))), "{line}");
+            assert_globals(&cli, &line);
         }
     }
-}
-
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let args_has_two_values = args.len() == 2;
 
-    if !(args_has_two_values) {
-        println!("\nusage: ./setup-coding <filename>");
+    #[test]
+    fn global_flags_after_a_subcommand() {
+        for subcommand in SUBCOMMANDS {
+            let line = format!("{subcommand} {GLOBALS}");
+            let cli = parse(&line);
 
-        process::exit(1);
+            assert!(!matches!(cli.command, Some(Command::File(_))), "{line}");
+            assert_globals(&cli, &line);
+        }
     }
 
-    let filename = &args[1];
-
-    println!("\nreading file: {filename}");
-
-    let contents = fs::read_to_string(filename).expect("couldn't read file");
-
-    let target_environment: TargetEnvironment =
-        toml::from_str(&contents).expect("error trying to convert toml file to string");
-
-    target_updates(target_environment.updates);
-
-    let architecture_name_output =
-        get_architecture_name_output().expect("architecture name process output error");
-
-    let architecture_name = convert_output_to_string(architecture_name_output)
-        .expect("architecture name conversion error");
-
-    let release_name_output = get_release_name_output().expect("release name process output error");
+    #[test]
+    fn subcommands_with_a_file() {
+        let cli = parse("--only tools plan x.toml");
 
-    let release_name =
-        convert_output_to_string(release_name_output).expect("release name conversion error");
+        assert!(
+            matches!(cli.command, Some(Command::Plan { file: Some(file) }) if file == Path::new("x.toml"))
+        );
+        assert_eq!(cli.global.only, ["tools"]);
 
-    let kernel_name_output = get_kernel_name_output().expect("kernel name process output error");
+        let cli = parse("check -q x.toml");
 
-    let kernel_name =
-        convert_output_to_string(kernel_name_output).expect("kernel name conversion error");
+        assert!(
+            matches!(cli.command, Some(Command::Check { file: Some(file) }) if file == Path::new("x.toml"))
+        );
+        assert!(cli.global.quiet);
 
-    let machine_hardware_name_output =
-        get_machine_hardware_name_output().expect("machine hardware name process output error");
+        let cli = parse("convert in.toml out.yaml");
 
-    let machine_hardware_name = convert_output_to_string(machine_hardware_name_output)
-        .expect("machine hardware name conversion error");
-
-    target_tools(
-        target_environment.tools,
-        &architecture_name,
-        &release_name,
-        &kernel_name,
-        &machine_hardware_name,
-    );
-    target_keys(target_environment.keys);
-}
+        assert!(matches!(
+            cli.command,
+            Some(Command::Convert {
+                file: Some(_),
+                output: Some(_),
+                to: None
+            })
+        ));
+    }
 
-fn target_keys(keys: Option<Keys>) {
-    println!("\nchecking targets for keys");
-    match keys {
-        None => {
-            println!("can't find keys in targets");
+    #[test]
+    fn a_bare_file_is_applied() {
+        for line in [
+            "x.toml",
+            "-q x.toml",
+            "x.toml -q",
+            "--config team.toml x.toml -q",
+        ] {
+            let cli = parse(line);
+
+            assert!(
+                matches!(&cli.command, Some(Command::Apply { file: Some(file) }) if file == Path::new("x.toml")),
+                "{line}"
+            );
+            assert!(!cli.dry_run, "{line}");
         }
-        Some(keys) => match keys.ssh {
-            None => {}
-            Some(ssh) => {
-                generate_new_ssh_key(&ssh.algorithm, &ssh.email, &ssh.title);
-            }
-        },
     }
-}
 
-fn target_tools(
-    tools: Option<Tools>,
-    architecture_name: &str,
-    release_name: &str,
-    kernel_name: &str,
-    machine_hardware_name: &str,
-) {
-    println!("\nchecking targets for tools");
-    match tools {
-        None => {
-            println!("can't find tools in targets");
-        }
-        Some(tools) => {
-            match tools.brave_browser {
-                None => {}
-                Some(_brave_browser) => {
-                    if !can_find_tool("brave-browser") {
-                        install_brave_browser();
-                    }
-                }
-            }
-            match tools.code {
-                None => {}
-                Some(_code) => {
-                    if !can_find_tool("code") {
-                        install_code();
-                    }
-                }
-            }
-            match tools.codecov {
-                None => {}
-                Some(_codecov) => {
-                    if !can_find_tool("codecov") {
-                        install_codecov();
-                    }
-                }
-            }
-            match tools.docker {
-                None => {}
-                Some(_docker) => {
-                    if !can_find_tool("docker") {
-                        install_docker(&architecture_name, &release_name);
-                    }
-                }
-            }
-            match tools.docker_compose {
-                None => {}
-                Some(docker_compose) => {
-                    if !can_find_version("docker-compose", &docker_compose.version) {
-                        install_docker_compose(
-                            &docker_compose.version,
-                            &kernel_name,
-                            &machine_hardware_name,
-                        );
-                    }
-                }
-            }
-            match tools.gh {
-                None => {}
-                Some(_gh) => {
-                    if !can_find_tool("gh") {
-                        install_gh(&architecture_name);
-                    }
-                }
-            }
-            match tools.git {
-                None => {}
-                Some(_git) => {
-                    if !can_find_tool("git") {
-                        install_git();
-                    }
-                }
-            }
-            match tools.google_chrome {
-                None => {}
-                Some(_google_chrome) => {
-                    if !can_find_tool("google-chrome") {
-                        install_google_chrome();
-                    }
-                }
-            }
-            match tools.node {
-                None => {}
-                Some(node) => {
-                    if !can_find_version("node", &node.version) {
-                        install_node(&node.version);
-                    }
-                }
-            }
-            match tools.rustc {
-                None => {}
-                Some(_rustc) => {
-                    if !can_find_tool("rustc") {
-                        install_rustc();
-                    }
-                }
-            }
-            match tools.solana {
-                None => {}
-                Some(solana) => {
-                    if !can_find_version("solana", &solana.version) {
-                        install_solana(&solana.version);
-                    }
-                }
-            }
+    #[test]
+    fn dry_run_before_or_after_a_bare_file() {
+        for line in [
+            "--dry-run x.toml",
+            "x.toml --dry-run",
+            "-q --dry-run x.toml",
+        ] {
+            let cli = parse(line);
+
+            assert!(cli.dry_run, "{line}");
+            assert!(
+                matches!(&cli.command, Some(Command::Apply { file: Some(file) }) if file == Path::new("x.toml")),
+                "{line}"
+            );
         }
     }
-}
 
-fn target_updates(updates: Option<Updates>) {
-    println!("\nchecking targets for updates");
-    match updates {
-        None => {
-            println!("can't find updates in targets");
-        }
-        Some(updates) => {
-            match updates.system {
-                None => {}
-                Some(system) => {
-                    if system {
-                        update_system();
-                    }
-                }
-            }
-            match updates.dependencies {
-                None => {}
-                Some(dependencies) => {
-                    if dependencies {
-                        update_dependencies();
-                    }
-                }
-            }
-            match updates.cleanup {
-                None => {}
-                Some(cleanup) => {
-                    if cleanup {
-                        update_cleanup();
-                    }
-                }
-            }
-        }
+    #[test]
+    fn no_arguments() {
+        let cli = parse("");
+
+        assert!(cli.command.is_none());
+        assert!(parse("--dry-run").dry_run);
     }
-}
 
-fn update_system() {
-    println!("\nupdating system");
-    let process = Command::new("sudo").arg("apt-get").arg("update").spawn();
+    #[test]
+    fn examples_convert_through_every_format_and_back() {
+        let global = parse("plan").global;
+        let examples = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        let directory =
+            std::env::temp_dir().join(format!("setup-coding-convert-{}", process::id()));
+
+        fs::create_dir_all(&directory).unwrap();
+
+        for entry in fs::read_dir(examples).unwrap() {
+            let example = entry.unwrap().path();
+            let name = example.file_stem().unwrap().to_str().unwrap();
+            let steps = [
+                (example.clone(), directory.join(format!("{name}.yaml"))),
+                (
+                    directory.join(format!("{name}.yaml")),
+                    directory.join(format!("{name}.json")),
+                ),
+                (
+                    directory.join(format!("{name}.json")),
+                    directory.join(format!("{name}.toml")),
+                ),
+            ];
+
+            for (file, output) in steps {
+                let printed = convert(&global, Some(file), Some(output), None).unwrap();
+
+                assert_eq!(printed, None, "{name}");
+            }
 
-    check_process_status("system updated", process);
-}
+            let original = convert(&global, Some(example.clone()), None, Some(Format::Toml));
+            let converted = fs::read_to_string(directory.join(format!("{name}.toml"))).unwrap();
 
-fn update_dependencies() {
-    // sudo apt install apt-transport-https build-essential ca-certificates clang cmake curl gnupg libappindicator3-dev libasound2-dev libgl1-mesa-dev libgtk-3-dev libpq-dev libssl-dev libudev-dev libvulkan-dev libwayland-dev libwebkit2gtk.0-dev libx11-dev libxi-dev libxcb-xfixes0-dev libxcb-shape0-dev libxkbcommon-dev lld lsb-release musl-tools nginx pkg-config postgresql-client python
-    let apt_install_dependencies_process = Command::new("sudo")
-        .arg("apt")
-        .arg("install")
-        .arg("apt-transport-https")
-        .arg("build-essential")
-        .arg("ca-certificates")
-        .arg("clang")
-        .arg("cmake")
-        .arg("curl")
-        .arg("gnupg")
-        .arg("libappindicator3-dev")
-        .arg("libasound2-dev")
-        .arg("libgl1-mesa-dev")
-        .arg("libgtk-3-dev")
-        .arg("libpq-dev")
-        .arg("libssl-dev")
-        .arg("libudev-dev")
-        .arg("libvulkan-dev")
-        .arg("libwayland-dev")
-        .arg("libwebkit2gtk-4.0-dev")
-        .arg("libx11-dev")
-        .arg("libxi-dev")
-        .arg("libxcb-xfixes0-dev")
-        .arg("libxcb-shape0-dev")
-        .arg("libxkbcommon-dev")
-        .arg("lld")
-        .arg("lsb-release")
-        .arg("musl-tools")
-        .arg("nginx")
-        .arg("pkg-config")
-        .arg("postgresql-client")
-        .arg("python")
-        .spawn();
-
-    check_process_status("installed dependencies", apt_install_dependencies_process);
-}
+            assert_eq!(original.unwrap(), Some(converted), "{name}");
+        }
 
-fn update_cleanup() {
-    // sudo apt install apt-transport-https build-essential ca-certificates curl gnupg lsb-release
-    let apt_autoremove_process = Command::new("sudo").arg("apt").arg("autoremove").spawn();
+        fs::remove_dir_all(&directory).unwrap();
+    }
 
-    check_process_status("system cleaned up", apt_autoremove_process);
+    #[test]
+    fn convert_needs_a_format_or_an_output() {
+        let args = ["setup-coding", "convert", "in.toml"]
+            .into_iter()
+            .map(OsString::from)
+            .collect();
+
+        assert!(parse_cli(args).is_err());
+    }
 }
@@ -0,0 +1,54 @@
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+/// How much progress is written to stderr. Errors and the summary are always written.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+}
+
+// set once from the command line, and read by every step that reports progress
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+static ASSUME_YES: AtomicBool = AtomicBool::new(false);
+
+pub fn set_verbosity(verbosity: Verbosity) {
+    VERBOSITY.store(verbosity as u8, Ordering::Relaxed);
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::Relaxed) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        _ => Verbosity::Verbose,
+    }
+}
+
+/// Whether package managers are told to go ahead without asking for confirmation.
+pub fn set_assume_yes(assume_yes: bool) {
+    ASSUME_YES.store(assume_yes, Ordering::Relaxed);
+}
+
+pub fn assume_yes() -> bool {
+    ASSUME_YES.load(Ordering::Relaxed)
+}
+
+/// Writes a progress line to stderr, unless `--quiet` was given.
+#[macro_export]
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if $crate::options::verbosity() >= $crate::options::Verbosity::Normal {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Writes a line to stderr only when `--verbose` was given.
+#[macro_export]
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::options::verbosity() >= $crate::options::Verbosity::Verbose {
+            eprintln!($($arg)*);
+        }
+    };
+}
@@ -0,0 +1,127 @@
+use crate::options;
+use crate::runner::CommandLine;
+use std::fmt;
+
+/// The distribution's package manager, which every package install and update goes through.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PackageManager {
+    Apk,
+    Apt,
+    Dnf,
+    Pacman,
+}
+
+/// One package under the name each backend knows it by; empty where a backend has no
+/// equivalent or does not need one.
+pub struct PackageNames {
+    pub apk: &'static [&'static str],
+    pub apt: &'static [&'static str],
+    pub dnf: &'static [&'static str],
+    pub pacman: &'static [&'static str],
+}
+
+impl PackageManager {
+    /// Picks the backend from a distribution's `ID` followed by its `ID_LIKE` entries.
+    pub fn from_distribution<'a>(ids: impl IntoIterator<Item = &'a str>) -> Option<PackageManager> {
+        ids.into_iter().find_map(|id| match id {
+            "alpine" => Some(PackageManager::Apk),
+            "debian" | "ubuntu" => Some(PackageManager::Apt),
+            "fedora" | "rhel" | "centos" => Some(PackageManager::Dnf),
+            "arch" => Some(PackageManager::Pacman),
+            _ => None,
+        })
+    }
+
+    pub fn package_names(self, names: &PackageNames) -> &'static [&'static str] {
+        match self {
+            PackageManager::Apk => names.apk,
+            PackageManager::Apt => names.apt,
+            PackageManager::Dnf => names.dnf,
+            PackageManager::Pacman => names.pacman,
+        }
+    }
+
+    /// The flag that answers yes to every confirmation, when `--yes` was given; apk never asks.
+    fn confirmation(self) -> Option<&'static str> {
+        if !options::assume_yes() {
+            return None;
+        }
+
+        match self {
+            PackageManager::Apk => None,
+            PackageManager::Apt | PackageManager::Dnf => Some("-y"),
+            PackageManager::Pacman => Some("--noconfirm"),
+        }
+    }
+
+    /// Refreshes the package index. Arch does not support refreshing without upgrading.
+    pub fn update(self) -> CommandLine {
+        match self {
+            PackageManager::Apk => CommandLine::new("sudo").args(["apk", "update"]),
+            PackageManager::Apt => CommandLine::new("sudo").args(["apt-get", "update"]),
+            PackageManager::Dnf => CommandLine::new("sudo").args(["dnf", "makecache"]),
+            PackageManager::Pacman => CommandLine::new("sudo")
+                .args(["pacman", "-Syu"])
+                .args(self.confirmation()),
+        }
+    }
+
+    pub fn install<S: AsRef<str>>(self, packages: &[S]) -> CommandLine {
+        let command = match self {
+            PackageManager::Apk => CommandLine::new("sudo").args(["apk", "add"]),
+            PackageManager::Apt => CommandLine::new("sudo").args(["apt-get", "install"]),
+            PackageManager::Dnf => CommandLine::new("sudo").args(["dnf", "install"]),
+            PackageManager::Pacman => CommandLine::new("sudo").args(["pacman", "-S", "--needed"]),
+        };
+
+        command
+            .args(self.confirmation())
+            .args(packages.iter().map(AsRef::as_ref))
+    }
+
+    /// A query that succeeds only when `package` is installed.
+    pub fn query_installed(self, package: &str) -> CommandLine {
+        match self {
+            PackageManager::Apk => CommandLine::new("apk").args(["info", "-e", package]),
+            PackageManager::Apt => CommandLine::new("dpkg").args(["-s", package]),
+            PackageManager::Dnf => CommandLine::new("rpm").args(["-q", package]),
+            PackageManager::Pacman => CommandLine::new("pacman").args(["-Q", package]),
+        }
+    }
+
+    pub fn remove<S: AsRef<str>>(self, packages: &[S]) -> CommandLine {
+        let command = match self {
+            PackageManager::Apk => CommandLine::new("sudo").args(["apk", "del"]),
+            PackageManager::Apt => CommandLine::new("sudo").args(["apt-get", "remove"]),
+            PackageManager::Dnf => CommandLine::new("sudo").args(["dnf", "remove"]),
+            PackageManager::Pacman => CommandLine::new("sudo").args(["pacman", "-Rs"]),
+        };
+
+        command
+            .args(self.confirmation())
+            .args(packages.iter().map(AsRef::as_ref))
+    }
+
+    /// Removes packages nothing depends on anymore; apk keeps no such packages around.
+    pub fn cleanup(self) -> Option<CommandLine> {
+        let command = match self {
+            PackageManager::Apk => return None,
+            PackageManager::Apt => CommandLine::new("sudo").args(["apt-get", "autoremove"]),
+            PackageManager::Dnf => CommandLine::new("sudo").args(["dnf", "autoremove"]),
+            PackageManager::Pacman => CommandLine::new("sudo").args(["pacman", "-Sc"]),
+        };
+
+        Some(command.args(self.confirmation()))
+    }
+}
+
+impl fmt::Display for PackageManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PackageManager::Apk => write!(f, "apk"),
+            PackageManager::Apt => write!(f, "apt"),
+            PackageManager::Dnf => write!(f, "dnf"),
+            PackageManager::Pacman => write!(f, "pacman"),
+        }
+    }
+}
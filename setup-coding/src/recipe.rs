@@ -0,0 +1,1713 @@
+use crate::checksum::verify_sha256;
+use crate::config::ToolSettings;
+use crate::error::Error;
+use crate::facts::Facts;
+use crate::keyring::{install_keyring, is_keyring_path};
+use crate::package_manager::PackageManager;
+use crate::progress;
+use crate::run_step;
+use crate::runner::{CommandLine, Runner};
+use crate::sources::Deb822Source;
+use crate::state_file::ToolRecord;
+use crate::verbose;
+use crate::version::{install_version, parse_version};
+use regex::Regex;
+use schemars::JsonSchema;
+use semver::{Version, VersionReq};
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use serde_derive::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::path::Path;
+
+/// Everything needed to detect and install one tool, see `recipes.toml`.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Recipe {
+    pub name: String,
+    /// Entries to set up before this tool: other tools, `system`, `dependencies` or `cleanup`.
+    /// They are included even when the config does not name them.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub requires: Vec<String>,
+    pub detect: Vec<Detect>,
+    pub install: Vec<Method>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Detect {
+    pub command: Vec<String>,
+    pub version_pattern: Option<String>,
+}
+
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct Method {
+    pub name: Option<String>,
+    #[schemars(with = "Option<String>")]
+    pub versions: Option<VersionReq>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub after: Vec<Vec<String>>,
+    /// Commands that reverse the install, in place of the ones derived from `kind`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub uninstall: Vec<Vec<String>>,
+    #[serde(flatten)]
+    pub kind: MethodKind,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum MethodKind {
+    Apk(Packages),
+    Apt(Apt),
+    Binary(Binary),
+    Dnf(Dnf),
+    Pacman(Packages),
+    Script(Script),
+    Snap(Snap),
+}
+
+/// The keys of a method that apply to every type.
+const METHOD_FIELDS: [&str; 4] = ["name", "versions", "after", "uninstall"];
+
+/// The keys of a method that apply to every type, split off from those of its type.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MethodFields {
+    name: Option<String>,
+    versions: Option<VersionReq>,
+    #[serde(default)]
+    after: Vec<Vec<String>>,
+    #[serde(default)]
+    uninstall: Vec<Vec<String>>,
+}
+
+impl<'de> Deserialize<'de> for Method {
+    /// Checks the keys of a method against those of every type and of its own type at
+    /// once, which `#[serde(flatten)]` does not, so that a misspelled key is reported with
+    /// all the keys it could have meant.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Method, D::Error> {
+        let mut kind_fields = Map::deserialize(deserializer)?;
+        let method_fields = METHOD_FIELDS
+            .iter()
+            .filter_map(|name| kind_fields.remove_entry(*name))
+            .collect::<Map<_, _>>();
+
+        // an unknown or missing type is reported by MethodKind
+        let type_fields = kind_fields
+            .get("type")
+            .and_then(Value::as_str)
+            .and_then(MethodKind::field_names);
+
+        if let Some(type_fields) = type_fields {
+            let unknown = kind_fields
+                .keys()
+                .find(|key| *key != "type" && !type_fields.contains(&key.as_str()));
+
+            if let Some(unknown) = unknown {
+                let expected = METHOD_FIELDS
+                    .iter()
+                    .chain(&["type"])
+                    .chain(type_fields)
+                    .map(|name| format!("`{name}`"))
+                    .collect::<Vec<_>>();
+
+                return Err(de::Error::custom(format!(
+                    "unknown field `{unknown}`, expected one of {}",
+                    expected.join(", ")
+                )));
+            }
+        }
+
+        let fields =
+            MethodFields::deserialize(Value::Object(method_fields)).map_err(de::Error::custom)?;
+        let kind =
+            MethodKind::deserialize(Value::Object(kind_fields)).map_err(de::Error::custom)?;
+
+        Ok(Method {
+            name: fields.name,
+            versions: fields.versions,
+            after: fields.after,
+            uninstall: fields.uninstall,
+            kind,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Apt {
+    pub packages: Vec<String>,
+    pub repository: Option<AptRepository>,
+    pub setup_script: Option<Script>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Dnf {
+    pub packages: Vec<String>,
+    pub repository: Option<DnfRepository>,
+    pub setup_script: Option<Script>,
+}
+
+/// A `.repo` file downloaded into `/etc/yum.repos.d`.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct DnfRepository {
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Packages {
+    pub packages: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AptRepository {
+    pub name: String,
+    pub uri: String,
+    pub suite: String,
+    pub components: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub architectures: Vec<String>,
+    pub key_url: String,
+    #[serde(default)]
+    pub key_armored: bool,
+    pub keyring: String,
+    pub fingerprints: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Binary {
+    pub url: String,
+    pub destination: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub machine_aliases: BTreeMap<String, String>,
+    #[serde(default = "default_sudo")]
+    pub sudo: bool,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub checksums: Vec<Checksum>,
+}
+
+/// The known digest of a binary for one version and uname machine name.
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Checksum {
+    pub version: Version,
+    pub machine: String,
+    pub sha256: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Script {
+    pub url: String,
+    #[serde(default = "default_shell")]
+    pub shell: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct Snap {
+    pub snap: String,
+    #[serde(default)]
+    pub classic: bool,
+}
+
+fn default_sudo() -> bool {
+    true
+}
+
+fn default_shell() -> Vec<String> {
+    vec!["sh".to_string()]
+}
+
+/// Values for the `{name}` placeholders in recipe templates.
+struct Variables<'a> {
+    tool: &'a str,
+    version: Option<&'a Version>,
+    facts: &'a Facts,
+    machine_aliases: Option<&'a BTreeMap<String, String>>,
+}
+
+impl Variables<'_> {
+    fn get(&self, name: &str) -> Result<String, Error> {
+        match name {
+            "version" | "major" => {
+                let version = self.version.ok_or_else(|| {
+                    Error::InvalidConfig(format!("{} needs a `version`", self.tool))
+                })?;
+
+                match name {
+                    "major" => Ok(version.major.to_string()),
+                    _ => Ok(version.to_string()),
+                }
+            }
+            "architecture" => Ok(self.facts.deb_architecture()?.to_string()),
+            "rpm_architecture" => Ok(self.facts.rpm_architecture()?.to_string()),
+            "id" => Ok(self.facts.id()?.to_string()),
+            "release" => Ok(self.facts.version_codename()?.to_string()),
+            "kernel" => Ok(self.facts.kernel_name()?.to_string()),
+            "os" => Ok(self.facts.kernel_name()?.to_lowercase()),
+            "machine" => {
+                let machine_hardware_name = self.facts.machine_hardware_name()?;
+
+                Ok(self
+                    .machine_aliases
+                    .and_then(|aliases| aliases.get(machine_hardware_name))
+                    .map_or(machine_hardware_name, String::as_str)
+                    .to_string())
+            }
+            "home" => {
+                env::var("HOME").map_err(|_| Error::MissingEnvironmentVariable("HOME".to_string()))
+            }
+            "docker_config" => match env::var("DOCKER_CONFIG") {
+                Ok(docker_config) => Ok(docker_config),
+                Err(_) => Ok(format!("{}/.docker", self.get("home")?)),
+            },
+            _ => Err(Error::InvalidRecipe {
+                tool: self.tool.to_string(),
+                reason: format!("unknown placeholder {{{name}}}"),
+            }),
+        }
+    }
+
+    fn expand(&self, template: &str) -> Result<String, Error> {
+        let mut expanded = String::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| Error::InvalidRecipe {
+                    tool: self.tool.to_string(),
+                    reason: format!("unclosed placeholder in `{template}`"),
+                })?
+                + start;
+
+            expanded.push_str(&rest[..start]);
+            expanded.push_str(&self.get(&rest[start + 1..end])?);
+            rest = &rest[end + 1..];
+        }
+
+        expanded.push_str(rest);
+
+        Ok(expanded)
+    }
+
+    fn command(&self, words: &[String]) -> Result<CommandLine, Error> {
+        let (program, args) = words.split_first().ok_or_else(|| Error::InvalidRecipe {
+            tool: self.tool.to_string(),
+            reason: "empty command".to_string(),
+        })?;
+
+        let mut command = CommandLine::new(&self.expand(program)?);
+
+        for arg in args {
+            command = command.arg(self.expand(arg)?);
+        }
+
+        Ok(command)
+    }
+}
+
+fn privileged(sudo: bool, program: &str) -> CommandLine {
+    if sudo {
+        CommandLine::new("sudo").arg(program)
+    } else {
+        CommandLine::new(program)
+    }
+}
+
+impl Detect {
+    fn find(
+        &self,
+        runner: &mut dyn Runner,
+        tool: &str,
+        requirement: Option<&VersionReq>,
+    ) -> Result<bool, Error> {
+        let (program, args) = self
+            .command
+            .split_first()
+            .ok_or_else(|| Error::InvalidRecipe {
+                tool: tool.to_string(),
+                reason: "empty detect command".to_string(),
+            })?;
+
+        let command = CommandLine::new(program).args(args);
+
+        let output = match runner.query(&command) {
+            Err(error) if error.is_not_found() => {
+                verbose!("{error}");
+
+                return Ok(false);
+            }
+            result => result?,
+        };
+
+        verbose!("read string: {}", output.stdout);
+        verbose!("process status: {}", output.status);
+
+        // `docker compose version` fails rather than being missing when compose is not there
+        if !output.status.is_success() {
+            return Ok(false);
+        }
+
+        let requirement = match requirement {
+            None => {
+                progress!("found tool: {tool}");
+
+                return Ok(true);
+            }
+            Some(requirement) => requirement,
+        };
+
+        let version_pattern = self
+            .version_pattern
+            .as_deref()
+            .ok_or_else(|| Error::InvalidConfig(format!("{tool} does not support a `version`")))?;
+
+        let version_pattern =
+            Regex::new(version_pattern).map_err(|error| Error::InvalidRecipe {
+                tool: tool.to_string(),
+                reason: error.to_string(),
+            })?;
+
+        match parse_version(&output.stdout, &version_pattern) {
+            None => {
+                progress!("could not find a version of {tool} in the output of `{command}`");
+
+                Ok(false)
+            }
+            Some(version) if requirement.matches(&version) => {
+                progress!(
+                    "found tool and version: {tool} {version}, which satisfies {requirement}"
+                );
+
+                Ok(true)
+            }
+            Some(version) => {
+                progress!("found tool and version: {tool} {version}, which does not satisfy {requirement}");
+
+                Ok(false)
+            }
+        }
+    }
+}
+
+impl AptRepository {
+    /// Downloads the signing key and writes the sources file, returning whether the sources
+    /// file changed.
+    fn add(
+        &self,
+        runner: &mut dyn Runner,
+        variables: &Variables,
+        record: &mut ToolRecord,
+    ) -> Result<bool, Error> {
+        let key_url = variables.expand(&self.key_url)?;
+        let keyring = variables.expand(&self.keyring)?;
+
+        if !is_keyring_path(&keyring) {
+            return Err(Error::InvalidRecipe {
+                tool: variables.tool.to_string(),
+                reason: format!(
+                    "keyring {keyring} is not in /usr/share/keyrings or /etc/apt/keyrings"
+                ),
+            });
+        }
+
+        if self.fingerprints.is_empty() {
+            return Err(Error::InvalidRecipe {
+                tool: variables.tool.to_string(),
+                reason: format!("repository {} pins no key fingerprints", self.name),
+            });
+        }
+
+        install_keyring(
+            runner,
+            &self.name,
+            &key_url,
+            self.key_armored,
+            &keyring,
+            &self.fingerprints,
+        )?;
+
+        record.sources.push(keyring.clone());
+
+        let source = Deb822Source {
+            types: vec!["deb".to_string()],
+            uris: vec![variables.expand(&self.uri)?],
+            suites: vec![variables.expand(&self.suite)?],
+            components: self.components.clone(),
+            architectures: self
+                .architectures
+                .iter()
+                .map(|architecture| variables.expand(architecture))
+                .collect::<Result<_, _>>()?,
+            signed_by: Some(keyring),
+        };
+
+        let contents = source.to_string();
+        let path = Deb822Source::path(&self.name);
+        let legacy_path = Deb822Source::legacy_path(&self.name);
+
+        record.sources.push(path.to_string_lossy().to_string());
+
+        if runner.read_file(&legacy_path)?.is_some() {
+            // sudo rm -f /etc/apt/sources.list.d/docker.list
+            run_step(
+                runner,
+                "removed legacy sources file",
+                &[CommandLine::new("sudo")
+                    .args(["rm", "-f"])
+                    .arg(legacy_path.to_string_lossy())],
+            )?;
+        }
+
+        if runner.read_file(&path)?.as_deref() == Some(contents.as_str()) {
+            progress!("sources file is up to date: {}", path.display());
+
+            return Ok(false);
+        }
+
+        runner.write_file(&path, &contents)?;
+
+        if !runner.is_dry_run() {
+            progress!("wrote sources file: {}", path.display());
+        }
+
+        Ok(true)
+    }
+
+    fn remove(&self, runner: &mut dyn Runner, variables: &Variables) -> Result<(), Error> {
+        // sudo rm -f /etc/apt/sources.list.d/docker.sources /etc/apt/sources.list.d/docker.list /etc/apt/keyrings/docker.gpg
+        run_step(
+            runner,
+            "removed sources file and keyring",
+            &[CommandLine::new("sudo")
+                .args(["rm", "-f"])
+                .arg(Deb822Source::path(&self.name).to_string_lossy())
+                .arg(Deb822Source::legacy_path(&self.name).to_string_lossy())
+                .arg(variables.expand(&self.keyring)?)],
+        )
+    }
+}
+
+impl Binary {
+    /// The configured digest, or else the built-in one for this version and machine.
+    fn expected_sha256<'a>(
+        &'a self,
+        variables: &Variables,
+        sha256: Option<&'a str>,
+    ) -> Result<Option<&'a str>, Error> {
+        if sha256.is_some() {
+            return Ok(sha256);
+        }
+
+        let version = match variables.version {
+            None => return Ok(None),
+            Some(version) => version,
+        };
+        let machine = variables.facts.machine_hardware_name()?;
+
+        Ok(self
+            .checksums
+            .iter()
+            .find(|checksum| &checksum.version == version && checksum.machine == machine)
+            .map(|checksum| checksum.sha256.as_str()))
+    }
+
+    fn install(
+        &self,
+        runner: &mut dyn Runner,
+        variables: &Variables,
+        sha256: Option<&str>,
+        record: &mut ToolRecord,
+    ) -> Result<(), Error> {
+        let directory = runner.create_private_directory()?;
+        let download_path = directory.join(variables.tool);
+
+        let result = self.download(runner, variables, &download_path, sha256, record);
+
+        if let Err(error) = runner.remove_private_directory(&directory) {
+            verbose!("{error}");
+        }
+
+        result
+    }
+
+    /// Downloads the binary into `download_path`, in a private directory so no one else
+    /// can swap it between the check and the install, and installs it from there.
+    fn download(
+        &self,
+        runner: &mut dyn Runner,
+        variables: &Variables,
+        download_path: &Path,
+        sha256: Option<&str>,
+        record: &mut ToolRecord,
+    ) -> Result<(), Error> {
+        let url = variables.expand(&self.url)?;
+        let destination = variables.expand(&self.destination)?;
+        let expected_sha256 = self.expected_sha256(variables, sha256)?;
+        let download = download_path.to_string_lossy().to_string();
+
+        // curl -fL https://uploader.codecov.io/latest/linux/codecov -o /tmp/setup-coding-a1B2c3/codecov
+        run_step(
+            runner,
+            "downloaded binary",
+            &[CommandLine::new("curl")
+                .arg("-fL")
+                .arg(&url)
+                .args(["-o", &download])],
+        )?;
+
+        match expected_sha256 {
+            None => progress!(
+                "no sha256 known for {}, not verifying {url}",
+                variables.tool
+            ),
+            Some(expected_sha256) => verify_sha256(runner, download_path, &url, expected_sha256)?,
+        }
+
+        self.install_download(runner, variables, &download, &destination, record)
+    }
+
+    fn install_download(
+        &self,
+        runner: &mut dyn Runner,
+        variables: &Variables,
+        download: &str,
+        destination: &str,
+        record: &mut ToolRecord,
+    ) -> Result<(), Error> {
+        if let Some(directory) = Path::new(destination).parent() {
+            // sudo mkdir -p /usr/local/bin
+            run_step(
+                runner,
+                "created directory",
+                &[privileged(self.sudo, "mkdir")
+                    .arg("-p")
+                    .arg(directory.to_string_lossy())],
+            )?;
+        }
+
+        // sudo install -m 755 /tmp/setup-coding-a1B2c3/codecov /usr/local/bin/codecov
+        run_step(
+            runner,
+            &format!("installed tool: {}", variables.tool),
+            &[privileged(self.sudo, "install").args(["-m", "755", download, destination])],
+        )?;
+
+        record.files.push(destination.to_string());
+
+        for link in &self.links {
+            let link = variables.expand(link)?;
+
+            // sudo ln -sf /usr/local/bin/docker-compose /usr/bin/docker-compose
+            run_step(
+                runner,
+                "linked binary",
+                &[privileged(self.sudo, "ln").args(["-sf", destination, &link])],
+            )?;
+
+            record.files.push(link);
+        }
+
+        Ok(())
+    }
+}
+
+impl DnfRepository {
+    fn remove(&self, runner: &mut dyn Runner) -> Result<(), Error> {
+        // sudo rm -f /etc/yum.repos.d/docker-ce.repo
+        run_step(
+            runner,
+            "removed repo file",
+            &[CommandLine::new("sudo")
+                .args(["rm", "-f"])
+                .arg(format!("/etc/yum.repos.d/{}.repo", self.name))],
+        )
+    }
+
+    fn add(
+        &self,
+        runner: &mut dyn Runner,
+        variables: &Variables,
+        record: &mut ToolRecord,
+    ) -> Result<(), Error> {
+        let repo_file = format!("/etc/yum.repos.d/{}.repo", self.name);
+
+        // sudo curl -fsSLo /etc/yum.repos.d/docker-ce.repo https://download.docker.com/linux/fedora/docker-ce.repo
+        run_step(
+            runner,
+            "downloaded repo file",
+            &[CommandLine::new("sudo")
+                .args(["curl", "-fsSLo", &repo_file])
+                .arg(variables.expand(&self.url)?)],
+        )?;
+
+        record.sources.push(repo_file);
+
+        Ok(())
+    }
+}
+
+impl Script {
+    fn run(
+        &self,
+        runner: &mut dyn Runner,
+        variables: &Variables,
+        message: &str,
+    ) -> Result<(), Error> {
+        // curl --proto =https --tlsv1.2 -fsSL https://sh.rustup.rs
+        // | sh
+        run_step(
+            runner,
+            message,
+            &[
+                CommandLine::new("curl")
+                    .args(["--proto", "=https", "--tlsv1.2", "-fsSL"])
+                    .arg(variables.expand(&self.url)?),
+                variables.command(&self.shell)?,
+            ],
+        )
+    }
+}
+
+impl Method {
+    /// The method's name, or its type for unnamed methods.
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(self.kind.type_name())
+    }
+}
+
+/// Removes the packages of a method that are installed, and nothing when none is, since
+/// removing a package that is not installed succeeds without removing the tool.
+fn remove_installed_packages(
+    runner: &mut dyn Runner,
+    package_manager: PackageManager,
+    packages: &[String],
+    message: &str,
+) -> Result<(), Error> {
+    let mut installed = Vec::new();
+
+    for package in packages {
+        let is_installed = match runner.query(&package_manager.query_installed(package)) {
+            Err(error) if error.is_not_found() => false,
+            result => result?.status.is_success(),
+        };
+
+        if is_installed {
+            installed.push(package);
+        }
+    }
+
+    if installed.is_empty() {
+        verbose!("none of {} is installed", packages.join(" "));
+
+        return Ok(());
+    }
+
+    run_step(runner, message, &[package_manager.remove(&installed)])
+}
+
+impl MethodKind {
+    fn uninstall(&self, runner: &mut dyn Runner, variables: &Variables) -> Result<(), Error> {
+        let removed_message = format!("removed tool: {}", variables.tool);
+
+        match self {
+            // sudo apk del docker
+            MethodKind::Apk(apk) => remove_installed_packages(
+                runner,
+                PackageManager::Apk,
+                &apk.packages,
+                &removed_message,
+            ),
+            MethodKind::Apt(apt) => {
+                // sudo apt-get remove docker-ce docker-ce-cli containerd.io
+                remove_installed_packages(
+                    runner,
+                    PackageManager::Apt,
+                    &apt.packages,
+                    &removed_message,
+                )?;
+
+                match &apt.repository {
+                    None => Ok(()),
+                    Some(repository) => repository.remove(runner, variables),
+                }
+            }
+            MethodKind::Binary(binary) => {
+                let mut command = privileged(binary.sudo, "rm")
+                    .args(["-f", &variables.expand(&binary.destination)?]);
+
+                for link in &binary.links {
+                    command = command.arg(variables.expand(link)?);
+                }
+
+                // sudo rm -f /usr/local/bin/docker-compose /usr/bin/docker-compose
+                run_step(runner, &removed_message, &[command])
+            }
+            MethodKind::Dnf(dnf) => {
+                // sudo dnf remove docker-ce docker-ce-cli containerd.io
+                remove_installed_packages(
+                    runner,
+                    PackageManager::Dnf,
+                    &dnf.packages,
+                    &removed_message,
+                )?;
+
+                match &dnf.repository {
+                    None => Ok(()),
+                    Some(repository) => repository.remove(runner),
+                }
+            }
+            // sudo pacman -Rs docker
+            MethodKind::Pacman(pacman) => remove_installed_packages(
+                runner,
+                PackageManager::Pacman,
+                &pacman.packages,
+                &removed_message,
+            ),
+            MethodKind::Script(_) => Err(Error::InvalidRecipe {
+                tool: variables.tool.to_string(),
+                reason: "installed by a script but has no `uninstall` commands".to_string(),
+            }),
+            // sudo snap remove code
+            MethodKind::Snap(snap) => run_step(
+                runner,
+                &removed_message,
+                &[CommandLine::new("sudo").args(["snap", "remove", &snap.snap])],
+            ),
+        }
+    }
+
+    /// Removes exactly what `record` says was installed.
+    fn uninstall_recorded(
+        &self,
+        runner: &mut dyn Runner,
+        variables: &Variables,
+        record: &ToolRecord,
+    ) -> Result<(), Error> {
+        let removed_message = format!("removed tool: {}", variables.tool);
+
+        match self {
+            MethodKind::Binary(binary) if !record.files.is_empty() => {
+                // sudo rm -f /usr/local/bin/docker-compose /usr/bin/docker-compose
+                run_step(
+                    runner,
+                    &removed_message,
+                    &[privileged(binary.sudo, "rm").arg("-f").args(&record.files)],
+                )?;
+            }
+            // sudo snap remove code
+            MethodKind::Snap(_) if !record.packages.is_empty() => run_step(
+                runner,
+                &removed_message,
+                &[CommandLine::new("sudo")
+                    .args(["snap", "remove"])
+                    .args(&record.packages)],
+            )?,
+            _ => match self.package_manager() {
+                // sudo apt-get remove docker-ce docker-ce-cli containerd.io
+                Some(package_manager) if !record.packages.is_empty() => run_step(
+                    runner,
+                    &removed_message,
+                    &[package_manager.remove(&record.packages)],
+                )?,
+                _ => return self.uninstall(runner, variables),
+            },
+        }
+
+        if !record.sources.is_empty() {
+            // sudo rm -f /etc/apt/keyrings/docker.gpg /etc/apt/sources.list.d/docker.sources
+            run_step(
+                runner,
+                "removed sources",
+                &[CommandLine::new("sudo")
+                    .args(["rm", "-f"])
+                    .args(&record.sources)],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// The keys that a method of the type named `type_name` takes besides `type`.
+    fn field_names(type_name: &str) -> Option<&'static [&'static str]> {
+        match type_name {
+            "apk" | "pacman" => Some(&["packages"]),
+            "apt" | "dnf" => Some(&["packages", "repository", "setup_script"]),
+            "binary" => Some(&[
+                "url",
+                "destination",
+                "links",
+                "machine_aliases",
+                "sudo",
+                "checksums",
+            ]),
+            "script" => Some(&["url", "shell"]),
+            "snap" => Some(&["snap", "classic"]),
+            _ => None,
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            MethodKind::Apk(_) => "apk",
+            MethodKind::Apt(_) => "apt",
+            MethodKind::Binary(_) => "binary",
+            MethodKind::Dnf(_) => "dnf",
+            MethodKind::Pacman(_) => "pacman",
+            MethodKind::Script(_) => "script",
+            MethodKind::Snap(_) => "snap",
+        }
+    }
+
+    /// The package manager a method needs, so methods for other distributions are passed over.
+    pub fn package_manager(&self) -> Option<PackageManager> {
+        match self {
+            MethodKind::Apk(_) => Some(PackageManager::Apk),
+            MethodKind::Apt(_) => Some(PackageManager::Apt),
+            MethodKind::Dnf(_) => Some(PackageManager::Dnf),
+            MethodKind::Pacman(_) => Some(PackageManager::Pacman),
+            MethodKind::Binary(_) | MethodKind::Script(_) | MethodKind::Snap(_) => None,
+        }
+    }
+
+    fn install(
+        &self,
+        runner: &mut dyn Runner,
+        variables: &Variables,
+        sha256: Option<&str>,
+        record: &mut ToolRecord,
+    ) -> Result<(), Error> {
+        if sha256.is_some() && !matches!(self, MethodKind::Binary(_)) {
+            return Err(Error::InvalidConfig(format!(
+                "{} does not download a binary to check a `sha256` against",
+                variables.tool
+            )));
+        }
+
+        let installed_message = format!("installed tool: {}", variables.tool);
+
+        match self {
+            MethodKind::Apk(apk) => {
+                // sudo apk add docker
+                run_step(
+                    runner,
+                    &installed_message,
+                    &[PackageManager::Apk.install(&apk.packages)],
+                )?;
+
+                record.packages.extend(apk.packages.iter().cloned());
+
+                Ok(())
+            }
+            MethodKind::Apt(apt) => {
+                if let Some(setup_script) = &apt.setup_script {
+                    setup_script.run(runner, variables, "set up repository")?;
+                }
+
+                let mut sources_changed = apt.setup_script.is_some();
+
+                if let Some(repository) = &apt.repository {
+                    sources_changed |= repository.add(runner, variables, record)?;
+                }
+
+                if sources_changed {
+                    // sudo apt-get update
+                    run_step(runner, "updated apt", &[PackageManager::Apt.update()])?;
+                }
+
+                // sudo apt-get install docker-ce docker-ce-cli containerd.io
+                run_step(
+                    runner,
+                    &installed_message,
+                    &[PackageManager::Apt.install(&apt.packages)],
+                )?;
+
+                record.packages.extend(apt.packages.iter().cloned());
+
+                Ok(())
+            }
+            MethodKind::Binary(binary) => binary.install(runner, variables, sha256, record),
+            MethodKind::Dnf(dnf) => {
+                if let Some(setup_script) = &dnf.setup_script {
+                    setup_script.run(runner, variables, "set up repository")?;
+                }
+
+                if let Some(repository) = &dnf.repository {
+                    repository.add(runner, variables, record)?;
+                }
+
+                // sudo dnf install docker-ce docker-ce-cli containerd.io
+                run_step(
+                    runner,
+                    &installed_message,
+                    &[PackageManager::Dnf.install(&dnf.packages)],
+                )?;
+
+                record.packages.extend(dnf.packages.iter().cloned());
+
+                Ok(())
+            }
+            MethodKind::Pacman(pacman) => {
+                // sudo pacman -S --needed docker
+                run_step(
+                    runner,
+                    &installed_message,
+                    &[PackageManager::Pacman.install(&pacman.packages)],
+                )?;
+
+                record.packages.extend(pacman.packages.iter().cloned());
+
+                Ok(())
+            }
+            MethodKind::Script(script) => script.run(runner, variables, &installed_message),
+            MethodKind::Snap(snap) => {
+                let mut command = CommandLine::new("sudo").args(["snap", "install", &snap.snap]);
+
+                if snap.classic {
+                    command = command.arg("--classic");
+                }
+
+                // sudo snap install code --classic
+                run_step(runner, &installed_message, &[command])?;
+
+                record.packages.push(snap.snap.clone());
+
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Recipe {
+    /// Whether any detect command finds the tool, at a version matching `requirement` if given.
+    pub fn find(
+        &self,
+        runner: &mut dyn Runner,
+        requirement: Option<&VersionReq>,
+    ) -> Result<bool, Error> {
+        match requirement {
+            None => progress!("\nchecking for tool: {}", self.name),
+            Some(requirement) => progress!(
+                "\nchecking for tool and version: {} {requirement}",
+                self.name
+            ),
+        }
+
+        for detect in &self.detect {
+            if detect.find(runner, &self.name, requirement)? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// The version of the installed tool, from the first detect command that reports one.
+    pub fn installed_version(&self, runner: &mut dyn Runner) -> Result<Option<Version>, Error> {
+        for detect in &self.detect {
+            let version_pattern = match &detect.version_pattern {
+                None => continue,
+                Some(version_pattern) => version_pattern,
+            };
+
+            let version_pattern =
+                Regex::new(version_pattern).map_err(|error| Error::InvalidRecipe {
+                    tool: self.name.clone(),
+                    reason: error.to_string(),
+                })?;
+
+            let command = match detect.command.split_first() {
+                None => continue,
+                Some((program, args)) => CommandLine::new(program).args(args),
+            };
+
+            let output = match runner.query(&command) {
+                Err(error) if error.is_not_found() => continue,
+                result => result?,
+            };
+
+            if let Some(version) = parse_version(&output.stdout, &version_pattern) {
+                return Ok(Some(version));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn select_method(
+        &self,
+        method_name: Option<&str>,
+        version: Option<&Version>,
+        package_manager: Option<PackageManager>,
+    ) -> Result<&Method, Error> {
+        let method = self
+            .install
+            .iter()
+            .filter(|method| method_name.is_none() || method.name.as_deref() == method_name)
+            .filter(|method| {
+                method
+                    .kind
+                    .package_manager()
+                    .is_none_or(|needed| Some(needed) == package_manager)
+            })
+            .find(|method| match (&method.versions, version) {
+                (None, _) => true,
+                (Some(versions), Some(version)) => versions.matches(version),
+                (Some(_), None) => false,
+            });
+
+        method.ok_or_else(|| {
+            let named = method_name
+                .map(|method_name| format!(" named `{method_name}`"))
+                .unwrap_or_default();
+            let named = match package_manager {
+                Some(package_manager) => format!("{named} with {package_manager}"),
+                None => format!("{named} without a known package manager"),
+            };
+
+            match version {
+                Some(version) => Error::InvalidConfig(format!(
+                    "{} has no install method{named} for version {version}",
+                    self.name
+                )),
+                None => Error::InvalidConfig(format!(
+                    "{} has no install method{named} that works without a `version`",
+                    self.name
+                )),
+            }
+        })
+    }
+
+    pub fn install(
+        &self,
+        runner: &mut dyn Runner,
+        settings: &ToolSettings,
+        facts: &Facts,
+    ) -> Result<ToolRecord, Error> {
+        progress!("\ninstalling tool: {}", self.name);
+
+        let version =
+            match &settings.version {
+                None => None,
+                Some(requirement) => Some(install_version(requirement).ok_or_else(|| {
+                    Error::UnresolvableVersion {
+                        tool: self.name.clone(),
+                        requirement: requirement.clone(),
+                    }
+                })?),
+            };
+
+        if let (Some(requirement), Some(version)) = (&settings.version, &version) {
+            if requirement.to_string() != format!("={version}") {
+                progress!("installing {version}, the lowest version that {requirement} allows");
+            }
+        }
+
+        let method = self.select_method(
+            settings.install.as_deref(),
+            version.as_ref(),
+            facts.package_manager().ok(),
+        )?;
+
+        let variables = Variables {
+            tool: &self.name,
+            version: version.as_ref(),
+            facts,
+            machine_aliases: match &method.kind {
+                MethodKind::Binary(binary) => Some(&binary.machine_aliases),
+                _ => None,
+            },
+        };
+
+        let mut record = ToolRecord {
+            method: method.label().to_string(),
+            ..ToolRecord::default()
+        };
+
+        method
+            .kind
+            .install(runner, &variables, settings.sha256.as_deref(), &mut record)?;
+
+        for command in &method.after {
+            let command = variables.command(command)?;
+            let message = format!("ran `{command}`");
+
+            run_step(runner, &message, &[command])?;
+        }
+
+        Ok(record)
+    }
+
+    /// Reverses the install method in `record`, or else every install method that could have
+    /// been used.
+    pub fn uninstall(
+        &self,
+        runner: &mut dyn Runner,
+        settings: &ToolSettings,
+        facts: &Facts,
+        record: Option<&ToolRecord>,
+    ) -> Result<(), Error> {
+        progress!("\nremoving tool: {}", self.name);
+
+        let package_manager = facts.package_manager().ok();
+        let methods: Vec<&Method> = self
+            .install
+            .iter()
+            .filter(|method| {
+                settings.install.is_none() || method.name.as_deref() == settings.install.as_deref()
+            })
+            .filter(|method| record.is_none_or(|record| method.label() == record.method))
+            .filter(|method| {
+                method
+                    .kind
+                    .package_manager()
+                    .is_none_or(|needed| Some(needed) == package_manager)
+            })
+            .collect();
+
+        if methods.is_empty() {
+            return Err(Error::InvalidConfig(format!(
+                "{} has no install method to reverse",
+                self.name
+            )));
+        }
+
+        for method in methods {
+            let variables = Variables {
+                tool: &self.name,
+                version: None,
+                facts,
+                machine_aliases: None,
+            };
+
+            match record {
+                _ if !method.uninstall.is_empty() => {}
+                None => method.kind.uninstall(runner, &variables)?,
+                Some(record) => method.kind.uninstall_recorded(runner, &variables, record)?,
+            }
+
+            for command in &method.uninstall {
+                let command = variables.command(command)?;
+                let message = format!("ran `{command}`");
+
+                run_step(runner, &message, &[command])?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::facts::DEBIAN;
+    use crate::registry::Registry;
+    use crate::runner::{DryRunRunner, Event, RecordingRunner, ScriptedRunner, Status};
+    use std::path::PathBuf;
+
+    const DOCKER_SOURCES: &str = "\
+Types: deb
+URIs: https://download.docker.com/linux/debian
+Suites: bookworm
+Components: stable
+Architectures: amd64
+Signed-By: /etc/apt/keyrings/docker.gpg
+";
+
+    const COMPOSE_SHA256: &str = "f3f10cf3dbb8107e9ba2ea5f23c1d2159ff7321d16f0a23051d68d8e2547b323";
+
+    fn compose_1() -> ToolSettings {
+        ToolSettings {
+            version: Some(VersionReq::parse("=1.29.2").unwrap()),
+            ..ToolSettings::default()
+        }
+    }
+
+    #[test]
+    fn binary_is_downloaded_into_a_private_directory() {
+        let facts = Facts::fixture(DEBIAN);
+        let registry = Registry::builtin().unwrap();
+        let mut runner = RecordingRunner::new(ScriptedRunner::new().on_query(
+            "sha256sum /tmp/setup-coding-XXXXXX/docker_compose",
+            &format!("{COMPOSE_SHA256}  /tmp/setup-coding-XXXXXX/docker_compose\n"),
+        ));
+
+        let record = registry
+            .get("docker_compose")
+            .unwrap()
+            .install(&mut runner, &compose_1(), &facts)
+            .unwrap();
+
+        assert_eq!(
+            runner.transcript(),
+            [
+                "create directory: /tmp/setup-coding-XXXXXX",
+                "run: curl -fL https://github.com/docker/compose/releases/download/1.29.2/docker-compose-Linux-x86_64 -o /tmp/setup-coding-XXXXXX/docker_compose",
+                "query: sha256sum /tmp/setup-coding-XXXXXX/docker_compose",
+                "run: sudo mkdir -p /usr/local/bin",
+                "run: sudo install -m 755 /tmp/setup-coding-XXXXXX/docker_compose /usr/local/bin/docker-compose",
+                "run: sudo ln -sf /usr/local/bin/docker-compose /usr/bin/docker-compose",
+                "remove directory: /tmp/setup-coding-XXXXXX",
+            ]
+        );
+        assert_eq!(
+            record.files,
+            ["/usr/local/bin/docker-compose", "/usr/bin/docker-compose"]
+        );
+    }
+
+    #[test]
+    fn binary_with_the_wrong_checksum_is_not_installed() {
+        let facts = Facts::fixture(DEBIAN);
+        let registry = Registry::builtin().unwrap();
+        let found = "0".repeat(64);
+        let mut runner = RecordingRunner::new(ScriptedRunner::new().on_query(
+            "sha256sum /tmp/setup-coding-XXXXXX/docker_compose",
+            &format!("{found}  /tmp/setup-coding-XXXXXX/docker_compose\n"),
+        ));
+
+        let error = registry
+            .get("docker_compose")
+            .unwrap()
+            .install(&mut runner, &compose_1(), &facts)
+            .unwrap_err();
+
+        assert!(
+            matches!(&error, Error::ChecksumMismatch { expected, .. } if expected == COMPOSE_SHA256),
+            "{error}"
+        );
+        // the download is removed without anything being installed
+        assert_eq!(
+            runner.transcript(),
+            [
+                "create directory: /tmp/setup-coding-XXXXXX",
+                "run: curl -fL https://github.com/docker/compose/releases/download/1.29.2/docker-compose-Linux-x86_64 -o /tmp/setup-coding-XXXXXX/docker_compose",
+                "query: sha256sum /tmp/setup-coding-XXXXXX/docker_compose",
+                "remove directory: /tmp/setup-coding-XXXXXX",
+            ]
+        );
+    }
+
+    #[test]
+    fn dry_run_prints_the_checksum_it_would_verify() {
+        let facts = Facts::fixture(DEBIAN);
+        let registry = Registry::builtin().unwrap();
+        let mut output = Vec::new();
+        let mut runner = DryRunRunner::with_output(ScriptedRunner::new(), &mut output);
+
+        registry
+            .get("docker_compose")
+            .unwrap()
+            .install(&mut runner, &compose_1(), &facts)
+            .unwrap();
+
+        let temporary = env::temp_dir().join("setup-coding-XXXXXX");
+
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            format!("\
+would run: curl -fL https://github.com/docker/compose/releases/download/1.29.2/docker-compose-Linux-x86_64 -o /tmp/setup-coding-XXXXXX/docker_compose
+would verify: sha256sum /tmp/setup-coding-XXXXXX/docker_compose, expecting {COMPOSE_SHA256}
+would run: sudo mkdir -p /usr/local/bin
+would run: sudo install -m 755 /tmp/setup-coding-XXXXXX/docker_compose /usr/local/bin/docker-compose
+would run: sudo ln -sf /usr/local/bin/docker-compose /usr/bin/docker-compose
+")
+            .replace("/tmp/setup-coding-XXXXXX", &temporary.to_string_lossy())
+        );
+    }
+
+    #[test]
+    fn failed_download_removes_the_private_directory() {
+        let facts = Facts::fixture(DEBIAN);
+        let registry = Registry::builtin().unwrap();
+        let mut runner = RecordingRunner::new(ScriptedRunner::new().on_run(
+            "curl -fL https://github.com/docker/compose/releases/download/1.29.2/docker-compose-Linux-x86_64 -o /tmp/setup-coding-XXXXXX/docker_compose",
+            Status::failure(22),
+        ));
+
+        let result =
+            registry
+                .get("docker_compose")
+                .unwrap()
+                .install(&mut runner, &compose_1(), &facts);
+
+        assert!(
+            matches!(result, Err(Error::NonZeroExit { .. })),
+            "{result:?}"
+        );
+        assert_eq!(
+            runner.transcript(),
+            [
+                "create directory: /tmp/setup-coding-XXXXXX",
+                "run: curl -fL https://github.com/docker/compose/releases/download/1.29.2/docker-compose-Linux-x86_64 -o /tmp/setup-coding-XXXXXX/docker_compose",
+                "remove directory: /tmp/setup-coding-XXXXXX",
+            ]
+        );
+    }
+
+    #[test]
+    fn apt_repository_is_added_before_installing() {
+        let facts = Facts::fixture(DEBIAN);
+        let registry = Registry::builtin().unwrap();
+        let mut runner = RecordingRunner::new(ScriptedRunner::new().on_query(
+            "gpg --show-keys --with-colons /tmp/setup-coding-XXXXXX/docker.key",
+            "pub:-:4096:1:8D81803C0EBFCD88:1487788586:::-:::scESA::::::23::0:\nfpr:::::::::9DC858229FC7DD38854AE2D88D81803C0EBFCD88:\n",
+        ));
+
+        let record = registry
+            .get("docker")
+            .unwrap()
+            .install(&mut runner, &ToolSettings::default(), &facts)
+            .unwrap();
+
+        assert_eq!(
+            runner.transcript(),
+            [
+                "create directory: /tmp/setup-coding-XXXXXX",
+                "run: curl -fsSLo /tmp/setup-coding-XXXXXX/docker.key https://download.docker.com/linux/debian/gpg",
+                "query: gpg --show-keys --with-colons /tmp/setup-coding-XXXXXX/docker.key",
+                "run: sudo mkdir -p /etc/apt/keyrings",
+                "run: sudo gpg --dearmor --yes -o /etc/apt/keyrings/docker.gpg /tmp/setup-coding-XXXXXX/docker.key",
+                "remove directory: /tmp/setup-coding-XXXXXX",
+                "read: /etc/apt/sources.list.d/docker.list",
+                "read: /etc/apt/sources.list.d/docker.sources",
+                "write: /etc/apt/sources.list.d/docker.sources",
+                "run: sudo apt-get update",
+                "run: sudo apt-get install docker-ce docker-ce-cli containerd.io",
+            ]
+        );
+        assert!(runner.events().contains(&Event::WriteFile(
+            PathBuf::from("/etc/apt/sources.list.d/docker.sources"),
+            DOCKER_SOURCES.to_string()
+        )));
+        assert_eq!(
+            record.sources,
+            [
+                "/etc/apt/keyrings/docker.gpg",
+                "/etc/apt/sources.list.d/docker.sources"
+            ]
+        );
+    }
+
+    #[test]
+    fn up_to_date_sources_are_left_alone() {
+        let facts = Facts::fixture(DEBIAN);
+        let registry = Registry::builtin().unwrap();
+        let mut runner = RecordingRunner::new(
+            ScriptedRunner::new()
+                .on_query(
+                    "gpg --show-keys --with-colons /tmp/setup-coding-XXXXXX/docker.key",
+                    "pub:-:4096:1:8D81803C0EBFCD88:1487788586:::-:::scESA::::::23::0:\nfpr:::::::::9DC858229FC7DD38854AE2D88D81803C0EBFCD88:\n",
+                )
+                .on_file("/etc/apt/sources.list.d/docker.sources", DOCKER_SOURCES),
+        );
+
+        let record = registry
+            .get("docker")
+            .unwrap()
+            .install(&mut runner, &ToolSettings::default(), &facts)
+            .unwrap();
+
+        assert_eq!(
+            runner.transcript(),
+            [
+                "create directory: /tmp/setup-coding-XXXXXX",
+                "run: curl -fsSLo /tmp/setup-coding-XXXXXX/docker.key https://download.docker.com/linux/debian/gpg",
+                "query: gpg --show-keys --with-colons /tmp/setup-coding-XXXXXX/docker.key",
+                "run: sudo mkdir -p /etc/apt/keyrings",
+                "run: sudo gpg --dearmor --yes -o /etc/apt/keyrings/docker.gpg /tmp/setup-coding-XXXXXX/docker.key",
+                "remove directory: /tmp/setup-coding-XXXXXX",
+                "read: /etc/apt/sources.list.d/docker.list",
+                "read: /etc/apt/sources.list.d/docker.sources",
+                "run: sudo apt-get install docker-ce docker-ce-cli containerd.io",
+            ]
+        );
+        assert!(!runner
+            .events()
+            .iter()
+            .any(|event| matches!(event, Event::WriteFile(..))));
+        // still recorded, so that removing docker removes them
+        assert_eq!(
+            record.sources,
+            [
+                "/etc/apt/keyrings/docker.gpg",
+                "/etc/apt/sources.list.d/docker.sources"
+            ]
+        );
+    }
+
+    #[test]
+    fn snap_is_installed_with_its_confinement() {
+        let facts = Facts::fixture(DEBIAN);
+        let registry = Registry::builtin().unwrap();
+        let mut runner = RecordingRunner::new(ScriptedRunner::new());
+
+        let record = registry
+            .get("code")
+            .unwrap()
+            .install(&mut runner, &ToolSettings::default(), &facts)
+            .unwrap();
+
+        assert_eq!(
+            runner.transcript(),
+            ["run: sudo snap install code --classic"]
+        );
+        assert_eq!(record.packages, ["code"]);
+    }
+
+    #[test]
+    fn script_is_piped_into_its_shell() {
+        let facts = Facts::fixture(DEBIAN);
+        let registry = Registry::builtin().unwrap();
+        let mut runner = RecordingRunner::new(ScriptedRunner::new());
+
+        registry
+            .get("rustc")
+            .unwrap()
+            .install(&mut runner, &ToolSettings::default(), &facts)
+            .unwrap();
+
+        assert_eq!(
+            runner.transcript(),
+            ["run: curl --proto =https --tlsv1.2 -fsSL https://sh.rustup.rs | sh"]
+        );
+    }
+
+    #[test]
+    fn dry_run_prints_what_it_would_do() {
+        assert_eq!(
+            print_dry_run("docker", None),
+            "\
+would run: curl -fsSLo /tmp/setup-coding-XXXXXX/docker.key https://download.docker.com/linux/debian/gpg
+would verify: gpg --show-keys --with-colons /tmp/setup-coding-XXXXXX/docker.key, expecting 9DC858229FC7DD38854AE2D88D81803C0EBFCD88
+would run: sudo mkdir -p /etc/apt/keyrings
+would run: sudo gpg --dearmor --yes -o /etc/apt/keyrings/docker.gpg /tmp/setup-coding-XXXXXX/docker.key
+would write: /etc/apt/sources.list.d/docker.sources
+    Types: deb
+    URIs: https://download.docker.com/linux/debian
+    Suites: bookworm
+    Components: stable
+    Architectures: amd64
+    Signed-By: /etc/apt/keyrings/docker.gpg
+would run: sudo apt-get update
+would run: sudo apt-get install docker-ce docker-ce-cli containerd.io
+"
+        );
+    }
+
+    /// What a dry run of installing `tool` prints, with the temporary directory as
+    /// `/tmp/setup-coding-XXXXXX` wherever it really is.
+    fn print_dry_run(tool: &str, version: Option<&str>) -> String {
+        let facts = Facts::fixture(DEBIAN);
+        let registry = Registry::builtin().unwrap();
+        let settings = ToolSettings {
+            version: version.map(|version| VersionReq::parse(version).unwrap()),
+            ..ToolSettings::default()
+        };
+        let mut output = Vec::new();
+        let mut runner = DryRunRunner::with_output(ScriptedRunner::new(), &mut output);
+
+        registry
+            .get(tool)
+            .unwrap()
+            .install(&mut runner, &settings, &facts)
+            .unwrap();
+
+        let temporary = env::temp_dir().join("setup-coding-XXXXXX");
+
+        String::from_utf8(output)
+            .unwrap()
+            .replace(&*temporary.to_string_lossy(), "/tmp/setup-coding-XXXXXX")
+    }
+
+    #[test]
+    fn dry_runs_print_what_each_recipe_would_do() {
+        let cases = [
+            (
+                "brave_browser",
+                None,
+                "\
+would run: curl -fsSLo /tmp/setup-coding-XXXXXX/brave-browser-release.key https://brave-browser-apt-release.s3.brave.com/brave-browser-archive-keyring.gpg
+would verify: gpg --show-keys --with-colons /tmp/setup-coding-XXXXXX/brave-browser-release.key, expecting D8BAD4DE7EE17AF52A834B2D0BB75829C2D4E821
+would run: sudo mkdir -p /usr/share/keyrings
+would run: sudo install -m 644 /tmp/setup-coding-XXXXXX/brave-browser-release.key /usr/share/keyrings/brave-browser-archive-keyring.gpg
+would write: /etc/apt/sources.list.d/brave-browser-release.sources
+    Types: deb
+    URIs: https://brave-browser-apt-release.s3.brave.com/
+    Suites: stable
+    Components: main
+    Architectures: amd64
+    Signed-By: /usr/share/keyrings/brave-browser-archive-keyring.gpg
+would run: sudo apt-get update
+would run: sudo apt-get install brave-browser
+",
+            ),
+            (
+                "codecov",
+                None,
+                "\
+would run: curl -fL https://uploader.codecov.io/latest/linux/codecov -o /tmp/setup-coding-XXXXXX/codecov
+would run: sudo mkdir -p /usr/local/bin
+would run: sudo install -m 755 /tmp/setup-coding-XXXXXX/codecov /usr/local/bin/codecov
+",
+            ),
+            (
+                "gh",
+                None,
+                "\
+would run: curl -fsSLo /tmp/setup-coding-XXXXXX/github-cli.key https://cli.github.com/packages/githubcli-archive-keyring.gpg
+would verify: gpg --show-keys --with-colons /tmp/setup-coding-XXXXXX/github-cli.key, expecting 2C6106201985B60E6C7AC87323F3D4EA75716059
+would run: sudo mkdir -p /usr/share/keyrings
+would run: sudo install -m 644 /tmp/setup-coding-XXXXXX/github-cli.key /usr/share/keyrings/githubcli-archive-keyring.gpg
+would write: /etc/apt/sources.list.d/github-cli.sources
+    Types: deb
+    URIs: https://cli.github.com/packages
+    Suites: stable
+    Components: main
+    Architectures: amd64
+    Signed-By: /usr/share/keyrings/githubcli-archive-keyring.gpg
+would run: sudo apt-get update
+would run: sudo apt-get install gh
+would run: gh auth login
+",
+            ),
+            ("git", None, "would run: sudo apt-get install git-all\n"),
+            (
+                "google_chrome",
+                None,
+                "\
+would run: curl -fsSLo /tmp/setup-coding-XXXXXX/google-chrome.key https://dl.google.com/linux/linux_signing_key.pub
+would verify: gpg --show-keys --with-colons /tmp/setup-coding-XXXXXX/google-chrome.key, expecting 4CCA1EAF950CEE4AB83976DCA040830F7FAC5991 EB4C1BFD4F042F6DDDCCEC917721F63BD38B4796
+would run: sudo mkdir -p /usr/share/keyrings
+would run: sudo gpg --dearmor --yes -o /usr/share/keyrings/google-chrome.gpg /tmp/setup-coding-XXXXXX/google-chrome.key
+would write: /etc/apt/sources.list.d/google-chrome.sources
+    Types: deb
+    URIs: http://dl.google.com/linux/chrome/deb/
+    Suites: stable
+    Components: main
+    Architectures: amd64
+    Signed-By: /usr/share/keyrings/google-chrome.gpg
+would run: sudo apt-get update
+would run: sudo apt-get install google-chrome-stable
+",
+            ),
+            (
+                "node",
+                Some("^18"),
+                "\
+would run: curl --proto =https --tlsv1.2 -fsSL https://deb.nodesource.com/setup_18.x | sudo -E bash -
+would run: sudo apt-get update
+would run: sudo apt-get install nodejs
+",
+            ),
+            (
+                "solana",
+                Some("=1.10.8"),
+                "would run: curl --proto =https --tlsv1.2 -fsSL https://release.solana.com/v1.10.8/install | sh\n",
+            ),
+        ];
+
+        for (tool, version, expected) in cases {
+            assert_eq!(print_dry_run(tool, version), expected, "{tool}");
+        }
+    }
+
+    fn parse_recipe(recipe: &str) -> Result<Recipe, String> {
+        toml::from_str(recipe).map_err(|error| error.message().to_string())
+    }
+
+    #[test]
+    fn recipes_reject_unknown_keys() {
+        let detect = parse_recipe(
+            r#"
+name = "foo"
+detect = [{ command = ["foo"], version_patern = "(.*)" }]
+install = []
+"#,
+        );
+        let method = parse_recipe(
+            r#"
+name = "foo"
+detect = [{ command = ["foo"] }]
+install = [{ type = "script", url = "https://example.com/install.sh", uninstal = [["rm", "foo"]] }]
+"#,
+        );
+        let method_type = parse_recipe(
+            r#"
+name = "foo"
+detect = [{ command = ["foo"] }]
+install = [{ type = "script", url = "https://example.com/install.sh", shel = ["bash"] }]
+"#,
+        );
+
+        assert!(detect
+            .unwrap_err()
+            .starts_with("unknown field `version_patern`"),);
+        assert_eq!(
+            method.unwrap_err(),
+            "unknown field `uninstal`, expected one of `name`, `versions`, `after`, `uninstall`, `type`, `url`, `shell`"
+        );
+        assert!(method_type.unwrap_err().starts_with("unknown field `shel`"));
+    }
+
+    #[test]
+    fn method_keys_split_between_the_method_and_its_type() {
+        let recipe = parse_recipe(
+            r#"
+name = "foo"
+detect = [{ command = ["foo"] }]
+install = [{ name = "pinned", versions = ">=2", type = "snap", snap = "foo", classic = true }]
+"#,
+        )
+        .unwrap();
+        let method = &recipe.install[0];
+
+        assert_eq!(method.label(), "pinned");
+        assert_eq!(method.versions, Some(VersionReq::parse(">=2").unwrap()));
+        assert!(
+            matches!(&method.kind, MethodKind::Snap(snap) if snap.snap == "foo" && snap.classic)
+        );
+    }
+
+    #[test]
+    fn method_field_names_match_the_types() {
+        let cases = [
+            ("apk", schemars::schema_for!(Packages)),
+            ("apt", schemars::schema_for!(Apt)),
+            ("binary", schemars::schema_for!(Binary)),
+            ("dnf", schemars::schema_for!(Dnf)),
+            ("pacman", schemars::schema_for!(Packages)),
+            ("script", schemars::schema_for!(Script)),
+            ("snap", schemars::schema_for!(Snap)),
+        ];
+
+        for (type_name, schema) in cases {
+            let mut expected = schema
+                .get("properties")
+                .and_then(|properties| properties.as_object())
+                .unwrap()
+                .keys()
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            let mut field_names = MethodKind::field_names(type_name).unwrap().to_vec();
+
+            expected.sort();
+            field_names.sort();
+
+            assert_eq!(field_names, expected, "{type_name}");
+        }
+    }
+}
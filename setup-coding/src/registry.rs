@@ -0,0 +1,49 @@
+use crate::error::Error;
+use crate::recipe::Recipe;
+use serde_derive::Deserialize;
+
+const BUILTIN_RECIPES: &str = include_str!("recipes.toml");
+
+#[derive(Debug, Deserialize)]
+struct RecipeFile {
+    tool: Vec<Recipe>,
+}
+
+/// The recipes that can be named under `[tools]`.
+#[derive(Debug)]
+pub struct Registry {
+    recipes: Vec<Recipe>,
+}
+
+impl Registry {
+    pub fn builtin() -> Result<Registry, Error> {
+        let recipe_file: RecipeFile =
+            toml::from_str(BUILTIN_RECIPES).map_err(Error::ParseRecipes)?;
+
+        Ok(Registry {
+            recipes: recipe_file.tool,
+        })
+    }
+
+    /// Adds a user-defined recipe, which may not shadow a built-in one.
+    pub fn add(&mut self, recipe: Recipe) -> Result<(), Error> {
+        if self.get(&recipe.name).is_some() {
+            return Err(Error::InvalidConfig(format!(
+                "custom tool {} is already defined",
+                recipe.name
+            )));
+        }
+
+        self.recipes.push(recipe);
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Recipe> {
+        self.recipes.iter().find(|recipe| recipe.name == name)
+    }
+
+    pub fn recipes(&self) -> &[Recipe] {
+        &self.recipes
+    }
+}
@@ -0,0 +1,126 @@
+use crate::progress;
+use serde_derive::Serialize;
+use std::fmt;
+
+/// The kinds of entries, in the order they are acted on unless prerequisites say otherwise.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Update,
+    Tool,
+    Key,
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Category::Update => write!(f, "update"),
+            Category::Tool => write!(f, "tool"),
+            Category::Key => write!(f, "key"),
+        }
+    }
+}
+
+/// What happened to one entry of the target environment. The string is shown as detail.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Succeeded(String),
+    Failed(String),
+    Skipped(String),
+}
+
+impl Outcome {
+    fn label(&self) -> &'static str {
+        match self {
+            Outcome::Succeeded(_) => "succeeded",
+            Outcome::Failed(_) => "failed",
+            Outcome::Skipped(_) => "skipped",
+        }
+    }
+
+    fn detail(&self) -> &str {
+        match self {
+            Outcome::Succeeded(detail) | Outcome::Failed(detail) | Outcome::Skipped(detail) => {
+                detail
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    pub category: Category,
+    pub name: String,
+    pub outcome: Outcome,
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    entries: Vec<Entry>,
+}
+
+impl Summary {
+    pub fn new() -> Summary {
+        Summary::default()
+    }
+
+    pub fn record(&mut self, category: Category, name: &str, outcome: Outcome) {
+        if let Outcome::Failed(ref message) = outcome {
+            progress!("failed {category}: {name}: {message}");
+        }
+
+        self.entries.push(Entry {
+            category,
+            name: name.to_string(),
+            outcome,
+        });
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn has_failures(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| matches!(entry.outcome, Outcome::Failed(_)))
+    }
+
+    fn count(&self, label: &str) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.outcome.label() == label)
+            .count()
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name_width = self
+            .entries
+            .iter()
+            .map(|entry| entry.name.len())
+            .max()
+            .unwrap_or(0);
+
+        for entry in &self.entries {
+            let line = format!(
+                "{:<6}  {:<name_width$}  {:<9}  {}",
+                entry.category.to_string(),
+                entry.name,
+                entry.outcome.label(),
+                entry.outcome.detail(),
+            );
+
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        write!(
+            f,
+            "{} succeeded, {} failed, {} skipped",
+            self.count("succeeded"),
+            self.count("failed"),
+            self.count("skipped"),
+        )
+    }
+}
@@ -0,0 +1,690 @@
+use crate::error::Error;
+use crate::verbose;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{CString, OsString};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+/// A program and its arguments, kept as data so it can be run, printed or recorded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommandLine {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl CommandLine {
+    pub fn new(program: &str) -> CommandLine {
+        CommandLine {
+            program: program.to_string(),
+            args: Vec::new(),
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> CommandLine {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> CommandLine
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    fn to_command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command.args(&self.args);
+        command
+    }
+}
+
+impl fmt::Display for CommandLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format_argument(&self.program))?;
+
+        for arg in &self.args {
+            write!(f, " {}", format_argument(arg))?;
+        }
+
+        Ok(())
+    }
+}
+
+fn format_argument(argument: &str) -> String {
+    let is_plain = !argument.is_empty()
+        && argument
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./:=@+,%".contains(c));
+
+    if is_plain {
+        argument.to_string()
+    } else {
+        format!("'{}'", argument.replace('\'', "'\\''"))
+    }
+}
+
+/// Renders a pipeline the way it would be typed into a shell.
+pub fn format_pipeline(commands: &[CommandLine]) -> String {
+    commands
+        .iter()
+        .map(CommandLine::to_string)
+        .collect::<Vec<_>>()
+        .join(" | ")
+}
+
+/// How a process finished; `code` is `None` when it was killed by a signal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Status {
+    pub code: Option<i32>,
+}
+
+impl Status {
+    pub fn success() -> Status {
+        Status { code: Some(0) }
+    }
+
+    pub fn failure(code: i32) -> Status {
+        Status { code: Some(code) }
+    }
+
+    pub fn is_success(&self) -> bool {
+        self.code == Some(0)
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.code {
+            Some(code) => write!(f, "exit status: {code}"),
+            None => write!(f, "terminated by signal"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Output {
+    pub status: Status,
+    pub stdout: String,
+}
+
+/// Executes the commands that the installers describe.
+///
+/// `run` is for commands that change the system and share the terminal with the user,
+/// `query` is for commands that only inspect the system and whose output is captured.
+/// Files are read and written the same way, `write_file` being the only one that changes
+/// the system.
+pub trait Runner {
+    fn run(&mut self, pipeline: &[CommandLine]) -> Result<Status, Error>;
+
+    fn query(&mut self, command: &CommandLine) -> Result<Output, Error>;
+
+    /// A query that checks something an earlier `run` made, like a download, against what
+    /// is `expected`.
+    ///
+    /// Runners that do not run anything have nothing to check; they answer `None` instead,
+    /// after reporting what would be checked.
+    fn verify(&mut self, command: &CommandLine, _expected: &str) -> Result<Option<Output>, Error> {
+        self.query(command).map(Some)
+    }
+
+    /// The contents of a file, or `None` when it does not exist.
+    fn read_file(&mut self, path: &Path) -> Result<Option<String>, Error>;
+
+    /// Replaces the contents of a file, going through sudo when it is not writable.
+    fn write_file(&mut self, path: &Path, contents: &str) -> Result<(), Error>;
+
+    /// Creates a directory with an unpredictable name that only the current user can enter,
+    /// for downloads to wait in until they are checked and installed.
+    fn create_private_directory(&mut self) -> Result<PathBuf, Error>;
+
+    /// Removes a directory from `create_private_directory` with everything in it.
+    fn remove_private_directory(&mut self, path: &Path) -> Result<(), Error>;
+
+    /// Whether `run` only describes commands instead of executing them.
+    fn is_dry_run(&self) -> bool {
+        false
+    }
+}
+
+/// Spawns real processes.
+#[derive(Debug, Default)]
+pub struct SystemRunner;
+
+impl Runner for SystemRunner {
+    fn run(&mut self, pipeline: &[CommandLine]) -> Result<Status, Error> {
+        verbose!("running: {}", format_pipeline(pipeline));
+
+        let spawn_error = |source: io::Error| Error::Spawn {
+            command: format_pipeline(pipeline),
+            source,
+        };
+
+        let (last, sources) = pipeline.split_last().ok_or_else(|| {
+            spawn_error(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "empty pipeline",
+            ))
+        })?;
+
+        let mut children: Vec<Child> = Vec::new();
+        let mut stdin: Option<Stdio> = None;
+
+        for source in sources {
+            let mut command = source.to_command();
+            command.stdout(Stdio::piped());
+
+            if let Some(stdin) = stdin.take() {
+                command.stdin(stdin);
+            }
+
+            let mut child = command.spawn().map_err(spawn_error)?;
+            stdin = child.stdout.take().map(Stdio::from);
+            children.push(child);
+        }
+
+        let mut command = last.to_command();
+
+        if let Some(stdin) = stdin {
+            command.stdin(stdin);
+        }
+
+        let status = command.spawn().and_then(|mut child| child.wait());
+
+        // like `set -o pipefail`, a failing source fails the whole pipeline
+        let mut source_status = Status::success();
+
+        for mut child in children {
+            let code = child.wait().map_err(spawn_error)?.code();
+
+            if source_status.is_success() {
+                source_status = Status { code };
+            }
+        }
+
+        let status = Status {
+            code: status.map_err(spawn_error)?.code(),
+        };
+
+        if status.is_success() {
+            Ok(source_status)
+        } else {
+            Ok(status)
+        }
+    }
+
+    fn query(&mut self, command: &CommandLine) -> Result<Output, Error> {
+        verbose!("querying: {command}");
+
+        let spawn_error = |source: io::Error| Error::Spawn {
+            command: command.to_string(),
+            source,
+        };
+
+        // stderr is only shown with --verbose: probing for a program that is missing or too
+        // old, like `docker compose version`, complains there and that is expected
+        let output = command
+            .to_command()
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .and_then(Child::wait_with_output)
+            .map_err(spawn_error)?;
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        if !stderr.trim().is_empty() {
+            verbose!("{}", stderr.trim_end());
+        }
+
+        let stdout =
+            String::from_utf8(output.stdout).map_err(|source| Error::UnreadableOutput {
+                command: command.to_string(),
+                source: io::Error::new(io::ErrorKind::InvalidData, source),
+            })?;
+
+        Ok(Output {
+            status: Status {
+                code: output.status.code(),
+            },
+            stdout,
+        })
+    }
+
+    fn read_file(&mut self, path: &Path) -> Result<Option<String>, Error> {
+        match fs::read_to_string(path) {
+            Err(source) if source.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(source) => Err(Error::ReadFile {
+                path: path.to_path_buf(),
+                source,
+            }),
+            Ok(contents) => Ok(Some(contents)),
+        }
+    }
+
+    fn write_file(&mut self, path: &Path, contents: &str) -> Result<(), Error> {
+        let write_error = |source: io::Error| Error::WriteFile {
+            path: path.to_path_buf(),
+            source,
+        };
+
+        match fs::write(path, contents) {
+            Err(source) if source.kind() == io::ErrorKind::PermissionDenied => {
+                let temporary = write_temporary_file(contents).map_err(write_error)?;
+
+                // sudo install -m 644 /tmp/setup-coding-1234 /etc/apt/sources.list.d/docker.sources
+                let command = CommandLine::new("sudo")
+                    .args(["install", "-m", "644"])
+                    .arg(temporary.to_string_lossy())
+                    .arg(path.to_string_lossy());
+
+                let status = self.run(std::slice::from_ref(&command));
+
+                if let Some(directory) = temporary.parent() {
+                    let _ = fs::remove_dir_all(directory);
+                }
+
+                match status? {
+                    status if status.is_success() => Ok(()),
+                    status => Err(Error::NonZeroExit {
+                        command: command.to_string(),
+                        status,
+                    }),
+                }
+            }
+            result => result.map_err(write_error),
+        }
+    }
+
+    fn create_private_directory(&mut self) -> Result<PathBuf, Error> {
+        make_private_directory().map_err(|source| Error::WriteFile {
+            path: env::temp_dir().join(PRIVATE_DIRECTORY_TEMPLATE),
+            source,
+        })
+    }
+
+    fn remove_private_directory(&mut self, path: &Path) -> Result<(), Error> {
+        fs::remove_dir_all(path).map_err(|source| Error::WriteFile {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+}
+
+/// What `mkdtemp` replaces to name a private directory, and what stands for the name when
+/// none is created.
+const PRIVATE_DIRECTORY_TEMPLATE: &str = "setup-coding-XXXXXX";
+
+/// Creates a directory in the temporary directory with mkdtemp(3), which makes it mode 700.
+fn make_private_directory() -> io::Result<PathBuf> {
+    let template = env::temp_dir().join(PRIVATE_DIRECTORY_TEMPLATE);
+    let mut template = CString::new(template.into_os_string().into_vec())?.into_bytes_with_nul();
+
+    // SAFETY: the template is nul terminated and mkdtemp only fills in its Xs
+    if unsafe { libc::mkdtemp(template.as_mut_ptr().cast()) }.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+
+    template.pop();
+
+    Ok(PathBuf::from(OsString::from_vec(template)))
+}
+
+/// Writes `contents` to a new file in a new private directory, for sudo to copy into place.
+///
+/// The directory is removed along with the file once it has been copied.
+fn write_temporary_file(contents: &str) -> io::Result<PathBuf> {
+    let directory = make_private_directory()?;
+    let path = directory.join("contents");
+
+    let written = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()));
+
+    if let Err(error) = written {
+        let _ = fs::remove_dir_all(&directory);
+
+        return Err(error);
+    }
+
+    Ok(path)
+}
+
+/// Prints commands that would change the system instead of running them.
+///
+/// Queries still go to the wrapped runner, since they only read the system and the
+/// printed plan depends on what is already installed. The plan goes to stdout unless
+/// another `output` is given.
+#[derive(Debug)]
+pub struct DryRunRunner<R: Runner = SystemRunner, W: Write = io::Stdout> {
+    inner: R,
+    output: W,
+}
+
+impl<R: Runner> DryRunRunner<R> {
+    pub fn new(inner: R) -> DryRunRunner<R> {
+        DryRunRunner::with_output(inner, io::stdout())
+    }
+}
+
+impl<R: Runner, W: Write> DryRunRunner<R, W> {
+    pub fn with_output(inner: R, output: W) -> DryRunRunner<R, W> {
+        DryRunRunner { inner, output }
+    }
+
+    fn print(&mut self, line: fmt::Arguments) -> Result<(), Error> {
+        writeln!(self.output, "{line}").map_err(Error::WriteOutput)
+    }
+}
+
+impl<R: Runner, W: Write> Runner for DryRunRunner<R, W> {
+    fn run(&mut self, pipeline: &[CommandLine]) -> Result<Status, Error> {
+        self.print(format_args!("would run: {}", format_pipeline(pipeline)))?;
+
+        Ok(Status::success())
+    }
+
+    fn query(&mut self, command: &CommandLine) -> Result<Output, Error> {
+        self.inner.query(command)
+    }
+
+    fn verify(&mut self, command: &CommandLine, expected: &str) -> Result<Option<Output>, Error> {
+        self.print(format_args!(
+            "would verify: {command}, expecting {expected}"
+        ))?;
+
+        Ok(None)
+    }
+
+    fn read_file(&mut self, path: &Path) -> Result<Option<String>, Error> {
+        self.inner.read_file(path)
+    }
+
+    fn write_file(&mut self, path: &Path, contents: &str) -> Result<(), Error> {
+        self.print(format_args!("would write: {}", path.display()))?;
+
+        for line in contents.lines() {
+            self.print(format_args!("    {line}"))?;
+        }
+
+        Ok(())
+    }
+
+    /// Creates nothing, the commands that would use the directory are only printed.
+    fn create_private_directory(&mut self) -> Result<PathBuf, Error> {
+        Ok(env::temp_dir().join(PRIVATE_DIRECTORY_TEMPLATE))
+    }
+
+    fn remove_private_directory(&mut self, _path: &Path) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn is_dry_run(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    Run(Vec<CommandLine>),
+    Query(CommandLine),
+    ReadFile(PathBuf),
+    WriteFile(PathBuf, String),
+    CreateDirectory(PathBuf),
+    RemoveDirectory(PathBuf),
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Event::Run(pipeline) => write!(f, "run: {}", format_pipeline(pipeline)),
+            Event::Query(command) => write!(f, "query: {command}"),
+            Event::ReadFile(path) => write!(f, "read: {}", path.display()),
+            Event::WriteFile(path, _) => write!(f, "write: {}", path.display()),
+            Event::CreateDirectory(path) => write!(f, "create directory: {}", path.display()),
+            Event::RemoveDirectory(path) => write!(f, "remove directory: {}", path.display()),
+        }
+    }
+}
+
+/// Passes every command on to the wrapped runner and keeps a transcript of them.
+#[derive(Debug, Default)]
+pub struct RecordingRunner<R: Runner> {
+    inner: R,
+    events: Vec<Event>,
+}
+
+impl<R: Runner> RecordingRunner<R> {
+    pub fn new(inner: R) -> RecordingRunner<R> {
+        RecordingRunner {
+            inner,
+            events: Vec::new(),
+        }
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    /// The transcript as one line per event, convenient for golden comparisons.
+    pub fn transcript(&self) -> Vec<String> {
+        self.events.iter().map(Event::to_string).collect()
+    }
+}
+
+impl<R: Runner> Runner for RecordingRunner<R> {
+    fn run(&mut self, pipeline: &[CommandLine]) -> Result<Status, Error> {
+        self.events.push(Event::Run(pipeline.to_vec()));
+        self.inner.run(pipeline)
+    }
+
+    fn query(&mut self, command: &CommandLine) -> Result<Output, Error> {
+        self.events.push(Event::Query(command.clone()));
+        self.inner.query(command)
+    }
+
+    fn verify(&mut self, command: &CommandLine, expected: &str) -> Result<Option<Output>, Error> {
+        self.events.push(Event::Query(command.clone()));
+        self.inner.verify(command, expected)
+    }
+
+    fn read_file(&mut self, path: &Path) -> Result<Option<String>, Error> {
+        self.events.push(Event::ReadFile(path.to_path_buf()));
+        self.inner.read_file(path)
+    }
+
+    fn write_file(&mut self, path: &Path, contents: &str) -> Result<(), Error> {
+        self.events
+            .push(Event::WriteFile(path.to_path_buf(), contents.to_string()));
+        self.inner.write_file(path, contents)
+    }
+
+    fn create_private_directory(&mut self) -> Result<PathBuf, Error> {
+        let path = self.inner.create_private_directory()?;
+
+        self.events.push(Event::CreateDirectory(path.clone()));
+
+        Ok(path)
+    }
+
+    fn remove_private_directory(&mut self, path: &Path) -> Result<(), Error> {
+        self.events.push(Event::RemoveDirectory(path.to_path_buf()));
+        self.inner.remove_private_directory(path)
+    }
+
+    fn is_dry_run(&self) -> bool {
+        self.inner.is_dry_run()
+    }
+}
+
+/// Answers commands from a script instead of spawning anything.
+///
+/// Commands are matched on their rendered form, e.g. `"node --version"` or
+/// `"curl -fsSL https://example.com | sudo -E bash -"`. Unscripted runs succeed and
+/// unscripted queries fail as if the program was not installed, which is what a fresh
+/// machine looks like. Files start out as scripted and keep whatever is written to them.
+#[derive(Debug, Default)]
+pub struct ScriptedRunner {
+    runs: HashMap<String, Status>,
+    queries: HashMap<String, Output>,
+    files: HashMap<PathBuf, String>,
+}
+
+impl ScriptedRunner {
+    pub fn new() -> ScriptedRunner {
+        ScriptedRunner::default()
+    }
+
+    pub fn on_run(mut self, pipeline: &str, status: Status) -> ScriptedRunner {
+        self.runs.insert(pipeline.to_string(), status);
+        self
+    }
+
+    pub fn on_query(mut self, command: &str, stdout: &str) -> ScriptedRunner {
+        self.queries.insert(
+            command.to_string(),
+            Output {
+                status: Status::success(),
+                stdout: stdout.to_string(),
+            },
+        );
+        self
+    }
+
+    pub fn on_file(mut self, path: &str, contents: &str) -> ScriptedRunner {
+        self.files.insert(PathBuf::from(path), contents.to_string());
+        self
+    }
+
+    pub fn file(&self, path: &str) -> Option<&str> {
+        self.files.get(Path::new(path)).map(String::as_str)
+    }
+}
+
+impl Runner for ScriptedRunner {
+    fn run(&mut self, pipeline: &[CommandLine]) -> Result<Status, Error> {
+        let key = format_pipeline(pipeline);
+
+        Ok(self.runs.get(&key).copied().unwrap_or_else(Status::success))
+    }
+
+    fn query(&mut self, command: &CommandLine) -> Result<Output, Error> {
+        let key = command.to_string();
+
+        self.queries.get(&key).cloned().ok_or_else(|| Error::Spawn {
+            command: key,
+            source: io::Error::new(io::ErrorKind::NotFound, "no scripted output"),
+        })
+    }
+
+    fn read_file(&mut self, path: &Path) -> Result<Option<String>, Error> {
+        Ok(self.files.get(path).cloned())
+    }
+
+    fn write_file(&mut self, path: &Path, contents: &str) -> Result<(), Error> {
+        self.files.insert(path.to_path_buf(), contents.to_string());
+
+        Ok(())
+    }
+
+    /// Always the same directory, so that transcripts do not change from run to run.
+    fn create_private_directory(&mut self) -> Result<PathBuf, Error> {
+        Ok(Path::new("/tmp").join(PRIVATE_DIRECTORY_TEMPLATE))
+    }
+
+    fn remove_private_directory(&mut self, _path: &Path) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn private_directories_are_new_and_only_for_the_user() {
+        let mut runner = SystemRunner;
+        let first = runner.create_private_directory().unwrap();
+        let second = runner.create_private_directory().unwrap();
+        let mode = fs::metadata(&first).unwrap().permissions().mode();
+
+        fs::write(first.join("download"), "").unwrap();
+        runner.remove_private_directory(&first).unwrap();
+        runner.remove_private_directory(&second).unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(mode & 0o777, 0o700);
+        assert!(!first.exists());
+    }
+
+    #[test]
+    fn temporary_files_are_in_a_private_directory() {
+        let first = write_temporary_file("Types: deb\n").unwrap();
+        let second = write_temporary_file("Types: deb\n").unwrap();
+        let directory = first.parent().unwrap();
+        let mode = fs::metadata(directory).unwrap().permissions().mode();
+        let contents = fs::read_to_string(&first).unwrap();
+
+        fs::remove_dir_all(directory).unwrap();
+        fs::remove_dir_all(second.parent().unwrap()).unwrap();
+
+        assert_ne!(first, second);
+        assert_eq!(mode & 0o777, 0o700);
+        assert_eq!(contents, "Types: deb\n");
+    }
+
+    /// Output whose reader has gone away, like `head` after its lines.
+    struct ClosedPipe;
+
+    impl Write for ClosedPipe {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn dry_run_returns_output_it_cannot_print() {
+        let mut runner = DryRunRunner::with_output(ScriptedRunner::new(), ClosedPipe);
+
+        let error = runner.run(&[CommandLine::new("true")]).unwrap_err();
+
+        assert!(
+            matches!(&error, Error::WriteOutput(source) if source.kind() == io::ErrorKind::BrokenPipe),
+            "{error}"
+        );
+    }
+
+    #[test]
+    fn queries_keep_stderr_out_of_the_output() {
+        let command =
+            CommandLine::new("sh").args(["-c", "echo found; echo 'unknown command' >&2; exit 3"]);
+        let output = SystemRunner.query(&command).unwrap();
+
+        assert_eq!(output.stdout, "found\n");
+        assert_eq!(output.status, Status::failure(3));
+    }
+
+    #[test]
+    fn queries_read_output_larger_than_a_pipe() {
+        // both pipes are drained at once, so a chatty stderr cannot stall the query
+        let command = CommandLine::new("sh")
+            .args(["-c", "head -c 200000 /dev/zero | tr '\\0' e >&2; echo done"]);
+        let output = SystemRunner.query(&command).unwrap();
+
+        assert_eq!(output.stdout, "done\n");
+    }
+}
@@ -0,0 +1,172 @@
+use crate::config::TargetEnvironment;
+use crate::error::Error;
+use crate::registry::Registry;
+use schemars::generate::SchemaSettings;
+use schemars::transform::{RecursiveTransform, Transform};
+use schemars::Schema;
+use serde_json::{json, Value};
+
+/// Drops the `null` that `Option` fields allow, since TOML has no null to write.
+fn remove_null(schema: &mut Schema) {
+    if let Some(Value::Array(types)) = schema.get_mut("type") {
+        types.retain(|name| name != "null");
+
+        if let [name] = types.as_slice() {
+            let name = name.clone();
+            schema.insert("type".to_string(), name);
+        }
+    }
+
+    if let Some(Value::Array(variants)) = schema.get_mut("anyOf") {
+        variants.retain(|variant| variant != &json!({ "type": "null" }));
+
+        if let [variant] = variants.as_slice() {
+            let variant = variant.clone();
+            schema.remove("anyOf");
+
+            if let Value::Object(variant) = variant {
+                for (key, value) in variant {
+                    schema.insert(key, value);
+                }
+            }
+        }
+    }
+}
+
+/// Closes each type of a recipe method to the keys of its type and those of every method.
+///
+/// Method types are flattened into the method, and in draft 7 `additionalProperties` only
+/// sees the properties of its own schema, so each type is inlined with the shared keys.
+fn close_method_types(schema: &mut Schema) {
+    let definitions = match schema.get("definitions") {
+        Some(Value::Object(definitions)) => definitions.clone(),
+        _ => return,
+    };
+
+    let method = match schema.pointer_mut("/definitions/Method") {
+        Some(Value::Object(method)) => method,
+        _ => return,
+    };
+
+    let shared = match method.remove("properties") {
+        Some(Value::Object(shared)) => shared,
+        _ => return,
+    };
+
+    let types = match method.get_mut("oneOf") {
+        Some(Value::Array(types)) => types,
+        _ => return,
+    };
+
+    for method_type in types.iter_mut().filter_map(Value::as_object_mut) {
+        let fields = method_type
+            .remove("allOf")
+            .and_then(|all_of| {
+                all_of
+                    .pointer("/0/$ref")
+                    .and_then(Value::as_str)
+                    .map(str::to_string)
+            })
+            .and_then(|reference| {
+                definitions
+                    .get(reference.trim_start_matches("#/definitions/"))
+                    .cloned()
+            })
+            .unwrap_or_default();
+
+        let properties = method_type
+            .entry("properties")
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .expect("the properties of a method type are an object");
+
+        if let Some(Value::Object(type_properties)) = fields.get("properties") {
+            properties.extend(type_properties.clone());
+        }
+
+        properties.extend(shared.clone());
+
+        if let (Some(Value::Array(required)), Some(Value::Array(type_required))) =
+            (method_type.get_mut("required"), fields.get("required"))
+        {
+            required.extend(type_required.iter().cloned());
+        }
+
+        method_type.insert("additionalProperties".to_string(), Value::Bool(false));
+    }
+}
+
+/// The JSON Schema of the config format, for editors like taplo to complete and lint with.
+///
+/// It is generated from the config types; the built-in tool names are added so that editors
+/// can complete them, while other names stay allowed for custom tools.
+pub fn schema() -> Result<Value, Error> {
+    let registry = Registry::builtin()?;
+    let mut schema = SchemaSettings::draft07()
+        .into_generator()
+        .into_root_schema_for::<TargetEnvironment>();
+
+    RecursiveTransform(remove_null).transform(&mut schema);
+    close_method_types(&mut schema);
+
+    if let Some(Value::Object(tools)) = schema.pointer_mut("/properties/tools") {
+        let tool_target = tools
+            .get("additionalProperties")
+            .cloned()
+            .unwrap_or(Value::Bool(true));
+        let properties = registry
+            .recipes()
+            .iter()
+            .map(|recipe| (recipe.name.clone(), tool_target.clone()))
+            .collect();
+
+        tools.insert("properties".to_string(), Value::Object(properties));
+    }
+
+    Ok(schema.to_value())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn method_types_only_allow_their_own_and_the_shared_keys() {
+        let schema = schema().unwrap();
+        let types = schema
+            .pointer("/definitions/Method/oneOf")
+            .and_then(Value::as_array)
+            .unwrap();
+
+        assert_eq!(types.len(), 7);
+
+        for method_type in types {
+            let properties = method_type["properties"].as_object().unwrap();
+
+            assert_eq!(method_type["additionalProperties"], json!(false));
+            assert!(method_type.get("allOf").is_none());
+
+            for shared in ["name", "versions", "after", "uninstall", "type"] {
+                assert!(properties.contains_key(shared), "{method_type}");
+            }
+        }
+
+        let script = &types[5];
+
+        assert_eq!(script["properties"]["type"]["const"], "script");
+        assert_eq!(script["required"], json!(["type", "url"]));
+    }
+
+    #[test]
+    fn recipe_settings_allow_no_other_keys() {
+        let schema = schema().unwrap();
+
+        for name in ["Recipe", "Detect", "AptRepository", "Script", "Checksum"] {
+            assert_eq!(
+                schema["definitions"][name]["additionalProperties"],
+                json!(false),
+                "{name}"
+            );
+        }
+    }
+}
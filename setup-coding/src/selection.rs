@@ -0,0 +1,71 @@
+use crate::config::{Keys, ToolTarget, Updates};
+use crate::report::Category;
+use std::collections::BTreeMap;
+
+/// Which entries of the target environment to act on, from `--only` and `--skip`.
+///
+/// Names are entry names like `node`, `dependencies` or `ssh`, or a whole category as
+/// `tools`, `updates` or `keys`. An empty `only` selects everything.
+#[derive(Clone, Debug, Default)]
+pub struct Selection {
+    pub only: Vec<String>,
+    pub skip: Vec<String>,
+}
+
+pub(crate) fn get_category_name(category: Category) -> &'static str {
+    match category {
+        Category::Update => "updates",
+        Category::Tool => "tools",
+        Category::Key => "keys",
+    }
+}
+
+fn names_match(names: &[String], category: Category, name: &str) -> bool {
+    let category_name = get_category_name(category);
+
+    names
+        .iter()
+        .any(|selected| selected == name || selected == category_name)
+}
+
+impl Selection {
+    pub fn includes(&self, category: Category, name: &str) -> bool {
+        (self.only.is_empty() || names_match(&self.only, category, name))
+            && !self.skips(category, name)
+    }
+
+    /// Whether `--skip` names the entry, which keeps it out even as a prerequisite.
+    pub fn skips(&self, category: Category, name: &str) -> bool {
+        names_match(&self.skip, category, name)
+    }
+
+    /// Drops the entries that are not selected, so they are neither applied nor reported.
+    pub fn retain(
+        &self,
+        updates: &mut Option<Updates>,
+        tools: &mut Option<BTreeMap<String, ToolTarget>>,
+        keys: &mut Option<Keys>,
+    ) {
+        if let Some(updates) = updates {
+            for (name, enabled) in [
+                ("system", &mut updates.system),
+                ("dependencies", &mut updates.dependencies),
+                ("cleanup", &mut updates.cleanup),
+            ] {
+                if !self.includes(Category::Update, name) {
+                    *enabled = None;
+                }
+            }
+        }
+
+        if let Some(tools) = tools {
+            tools.retain(|name, _| self.includes(Category::Tool, name));
+        }
+
+        if let Some(keys) = keys {
+            if !self.includes(Category::Key, "ssh") {
+                keys.ssh = None;
+            }
+        }
+    }
+}
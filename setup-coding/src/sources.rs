@@ -0,0 +1,113 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// One repository in the deb822 format of `/etc/apt/sources.list.d/*.sources`, see
+/// sources.list(5).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Deb822Source {
+    pub types: Vec<String>,
+    pub uris: Vec<String>,
+    pub suites: Vec<String>,
+    pub components: Vec<String>,
+    pub architectures: Vec<String>,
+    pub signed_by: Option<String>,
+}
+
+impl Deb822Source {
+    /// Where apt reads the repository named `name` from.
+    pub fn path(name: &str) -> PathBuf {
+        PathBuf::from(format!("/etc/apt/sources.list.d/{name}.sources"))
+    }
+
+    /// The one-line `.list` file that earlier versions wrote for the same repository.
+    pub fn legacy_path(name: &str) -> PathBuf {
+        PathBuf::from(format!("/etc/apt/sources.list.d/{name}.list"))
+    }
+}
+
+impl fmt::Display for Deb822Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let fields = [
+            ("Types", &self.types),
+            ("URIs", &self.uris),
+            ("Suites", &self.suites),
+            ("Components", &self.components),
+            ("Architectures", &self.architectures),
+        ];
+
+        for (name, values) in fields {
+            if !values.is_empty() {
+                writeln!(f, "{name}: {}", values.join(" "))?;
+            }
+        }
+
+        if let Some(signed_by) = &self.signed_by {
+            writeln!(f, "Signed-By: {signed_by}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn formats_every_field_in_order() {
+        let source = Deb822Source {
+            types: strings(&["deb", "deb-src"]),
+            uris: strings(&["https://download.docker.com/linux/debian"]),
+            suites: strings(&["bookworm"]),
+            components: strings(&["stable", "test"]),
+            architectures: strings(&["amd64", "arm64"]),
+            signed_by: Some("/etc/apt/keyrings/docker.gpg".to_string()),
+        };
+
+        assert_eq!(
+            source.to_string(),
+            "\
+Types: deb deb-src
+URIs: https://download.docker.com/linux/debian
+Suites: bookworm
+Components: stable test
+Architectures: amd64 arm64
+Signed-By: /etc/apt/keyrings/docker.gpg
+"
+        );
+    }
+
+    #[test]
+    fn leaves_out_empty_fields() {
+        // a flat repository has no components, and without architectures apt uses its own
+        let source = Deb822Source {
+            types: strings(&["deb"]),
+            uris: strings(&["https://example.com/apt"]),
+            suites: strings(&["./"]),
+            components: Vec::new(),
+            architectures: Vec::new(),
+            signed_by: None,
+        };
+
+        assert_eq!(
+            source.to_string(),
+            "Types: deb\nURIs: https://example.com/apt\nSuites: ./\n"
+        );
+    }
+
+    #[test]
+    fn names_the_files_of_a_repository() {
+        assert_eq!(
+            Deb822Source::path("docker"),
+            PathBuf::from("/etc/apt/sources.list.d/docker.sources")
+        );
+        assert_eq!(
+            Deb822Source::legacy_path("docker"),
+            PathBuf::from("/etc/apt/sources.list.d/docker.list")
+        );
+    }
+}
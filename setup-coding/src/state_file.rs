@@ -0,0 +1,210 @@
+use crate::error::Error;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What setup-coding did to install one tool, so later runs can tell drift, undo it and
+/// tell it apart from tools installed by hand.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ToolRecord {
+    pub method: String,
+    pub version: Option<String>,
+    #[serde(default)]
+    pub packages: Vec<String>,
+    #[serde(default)]
+    pub files: Vec<String>,
+    #[serde(default)]
+    pub sources: Vec<String>,
+    pub installed_at: String,
+    pub checked_at: String,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Contents {
+    #[serde(default)]
+    tools: BTreeMap<String, ToolRecord>,
+}
+
+/// The tools recorded in `$XDG_STATE_HOME/setup-coding/state.toml`.
+#[derive(Debug)]
+pub struct StateFile {
+    path: PathBuf,
+    contents: Contents,
+}
+
+/// `$XDG_STATE_HOME/setup-coding/state.toml`, where `$XDG_STATE_HOME` defaults to
+/// `$HOME/.local/state`.
+pub fn get_state_path() -> Result<PathBuf, Error> {
+    let state_home = match env::var("XDG_STATE_HOME") {
+        Ok(state_home) if !state_home.is_empty() => PathBuf::from(state_home),
+        _ => match env::var("HOME") {
+            Ok(home) => PathBuf::from(home).join(".local/state"),
+            Err(_) => return Err(Error::MissingEnvironmentVariable("HOME".to_string())),
+        },
+    };
+
+    Ok(state_home.join("setup-coding").join("state.toml"))
+}
+
+/// Formats a time as an RFC 3339 timestamp in UTC, e.g. `2022-05-01T12:30:00Z`.
+pub fn format_timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let days = (seconds / 86_400) as i64;
+    let seconds_of_day = seconds % 86_400;
+
+    // days to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        seconds_of_day / 3_600,
+        seconds_of_day % 3_600 / 60,
+        seconds_of_day % 60
+    )
+}
+
+pub fn now() -> String {
+    format_timestamp(SystemTime::now())
+}
+
+impl StateFile {
+    /// Reads the state file, which is empty before the first run.
+    pub fn load() -> Result<StateFile, Error> {
+        StateFile::load_from(get_state_path()?)
+    }
+
+    fn load_from(path: PathBuf) -> Result<StateFile, Error> {
+        let contents = match fs::read_to_string(&path) {
+            Err(source) if source.kind() == io::ErrorKind::NotFound => Contents::default(),
+            Err(source) => return Err(Error::ReadFile { path, source }),
+            Ok(contents) => toml::from_str(&contents).map_err(|source| Error::ParseState {
+                path: path.clone(),
+                source,
+            })?,
+        };
+
+        Ok(StateFile { path, contents })
+    }
+
+    /// A state file with nothing recorded, which is never saved.
+    #[cfg(test)]
+    pub fn empty() -> StateFile {
+        StateFile {
+            path: PathBuf::from("state.toml"),
+            contents: Contents::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Error> {
+        let write_error = |source| Error::WriteFile {
+            path: self.path.clone(),
+            source,
+        };
+
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory).map_err(write_error)?;
+        }
+
+        let contents = toml::to_string(&self.contents).map_err(Error::SerializeState)?;
+
+        fs::write(&self.path, contents).map_err(write_error)
+    }
+
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+
+    pub fn tools(&self) -> &BTreeMap<String, ToolRecord> {
+        &self.contents.tools
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ToolRecord> {
+        self.contents.tools.get(name)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut ToolRecord> {
+        self.contents.tools.get_mut(name)
+    }
+
+    pub fn insert(&mut self, name: &str, record: ToolRecord) {
+        self.contents.tools.insert(name.to_string(), record);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<ToolRecord> {
+        self.contents.tools.remove(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process;
+    use std::time::Duration;
+
+    #[test]
+    fn timestamps_are_rfc_3339_in_utc() {
+        let cases = [
+            (0, "1970-01-01T00:00:00Z"),
+            // a leap day
+            (1_709_209_805, "2024-02-29T12:30:05Z"),
+            // after 32-bit seconds run out in 2038
+            (2_208_988_800, "2040-01-01T00:00:00Z"),
+            // 2100 is no leap year, so march follows february 28th
+            (4_107_628_799, "2100-03-01T23:59:59Z"),
+        ];
+
+        for (seconds, expected) in cases {
+            let time = UNIX_EPOCH + Duration::from_secs(seconds);
+
+            assert_eq!(format_timestamp(time), expected, "{seconds}");
+        }
+    }
+
+    #[test]
+    fn saved_records_load_back() {
+        let directory = env::temp_dir().join(format!("setup-coding-state-{}", process::id()));
+        let path = directory.join("setup-coding").join("state.toml");
+        let record = ToolRecord {
+            method: "binary".to_string(),
+            version: Some("1.29.2".to_string()),
+            packages: Vec::new(),
+            files: vec!["/usr/local/bin/docker-compose".to_string()],
+            sources: Vec::new(),
+            installed_at: "2024-02-29T12:30:05Z".to_string(),
+            checked_at: "2024-03-01T08:00:00Z".to_string(),
+        };
+
+        let mut state_file = StateFile::load_from(path.clone()).unwrap();
+        let was_empty = state_file.tools().is_empty();
+
+        state_file.insert("docker_compose", record.clone());
+
+        let saved = state_file.save();
+        let loaded = StateFile::load_from(path);
+
+        fs::remove_dir_all(&directory).unwrap();
+
+        assert!(was_empty);
+        assert!(saved.is_ok(), "{saved:?}");
+        assert_eq!(loaded.unwrap().get("docker_compose"), Some(&record));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::config::{State, ToolSettings, ToolTarget};
+use crate::error::Error;
+use crate::facts::Facts;
+use crate::recipe::Recipe;
+use crate::registry::Registry;
+use crate::report::Outcome;
+use crate::runner::Runner;
+use crate::state_file::{self, StateFile};
+use std::collections::BTreeMap;
+
+pub fn get_tool_settings(name: &str, tool_target: ToolTarget) -> Result<ToolSettings, Error> {
+    match tool_target {
+        ToolTarget::Keyword(keyword) if keyword == "latest" => Ok(ToolSettings::default()),
+        ToolTarget::Keyword(keyword) if keyword == "absent" => Ok(ToolSettings {
+            state: State::Absent,
+            ..ToolSettings::default()
+        }),
+        ToolTarget::Keyword(keyword) => Err(Error::InvalidConfig(format!(
+            "{name} = \"{keyword}\" is not supported, use \"latest\", \"absent\" or a table with a `version`"
+        ))),
+        ToolTarget::Settings(settings) => Ok(settings),
+    }
+}
+
+/// Registers each custom tool and targets it, as `"latest"` unless `[tools]` already names it.
+pub fn add_custom_tools(
+    registry: &mut Registry,
+    tools: &mut Option<BTreeMap<String, ToolTarget>>,
+    custom_tools: Option<Vec<Recipe>>,
+) -> Result<(), Error> {
+    for recipe in custom_tools.unwrap_or_default() {
+        tools
+            .get_or_insert_with(BTreeMap::new)
+            .entry(recipe.name.clone())
+            .or_insert_with(|| ToolTarget::Keyword("latest".to_string()));
+
+        registry.add(recipe)?;
+    }
+
+    Ok(())
+}
+
+pub fn target_tool(
+    runner: &mut dyn Runner,
+    registry: &Registry,
+    facts: &Facts,
+    state_file: &mut StateFile,
+    name: &str,
+    tool_target: ToolTarget,
+) -> Result<Outcome, Error> {
+    let recipe = registry
+        .get(name)
+        .ok_or_else(|| Error::InvalidConfig(format!("unknown tool: {name}")))?;
+    let settings = get_tool_settings(name, tool_target)?;
+
+    if settings.state == State::Absent {
+        if !recipe.find(runner, None)? {
+            state_file.remove(name);
+
+            return Ok(Outcome::Skipped("not installed".to_string()));
+        }
+
+        recipe.uninstall(runner, &settings, facts, state_file.get(name))?;
+
+        if runner.is_dry_run() {
+            return Ok(Outcome::Succeeded("dry run".to_string()));
+        }
+
+        // a package manager removing nothing still succeeds
+        if recipe.find(runner, None)? {
+            return Ok(Outcome::Failed(
+                "still installed after removing it, it may have been installed another way"
+                    .to_string(),
+            ));
+        }
+
+        state_file.remove(name);
+
+        return Ok(Outcome::Succeeded("removed".to_string()));
+    }
+
+    if recipe.find(runner, settings.version.as_ref())? {
+        let record = match state_file.get_mut(name) {
+            None => {
+                return Ok(Outcome::Skipped(
+                    "already installed, not by setup-coding".to_string(),
+                ))
+            }
+            Some(record) => record,
+        };
+
+        let version = recipe
+            .installed_version(runner)?
+            .map(|version| version.to_string());
+        let recorded_version = record.version.clone();
+
+        if !runner.is_dry_run() {
+            record.checked_at = state_file::now();
+            record.version = version.clone().or(recorded_version.clone());
+        }
+
+        return match (recorded_version, version) {
+            (Some(recorded_version), Some(version)) if recorded_version != version => {
+                Ok(Outcome::Skipped(format!(
+                    "already installed, changed from {recorded_version} to {version} since it was installed"
+                )))
+            }
+            _ => Ok(Outcome::Skipped("already installed".to_string())),
+        };
+    }
+
+    let went_missing = state_file.get(name).is_some();
+    let mut record = recipe.install(runner, &settings, facts)?;
+
+    if runner.is_dry_run() {
+        return Ok(Outcome::Succeeded("dry run".to_string()));
+    }
+
+    let now = state_file::now();
+
+    record.version = recipe
+        .installed_version(runner)?
+        .map(|version| version.to_string());
+    record.installed_at = now.clone();
+    record.checked_at = now;
+
+    state_file.insert(name, record);
+
+    match went_missing {
+        true => Ok(Outcome::Succeeded(
+            "reinstalled, it had been removed since it was installed".to_string(),
+        )),
+        false => Ok(Outcome::Succeeded("installed".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::facts::DEBIAN;
+    use crate::runner::{RecordingRunner, ScriptedRunner};
+
+    fn absent() -> ToolTarget {
+        ToolTarget::Keyword("absent".to_string())
+    }
+
+    #[test]
+    fn absent_tool_removes_only_installed_packages() {
+        let facts = Facts::fixture(DEBIAN);
+        let registry = Registry::builtin().unwrap();
+        let mut runner = RecordingRunner::new(
+            ScriptedRunner::new()
+                .on_query("git --version", "git version 2.39.5\n")
+                .on_query("dpkg -s git-all", "Status: install ok installed\n"),
+        );
+
+        let outcome = target_tool(
+            &mut runner,
+            &registry,
+            &facts,
+            &mut StateFile::empty(),
+            "git",
+            absent(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            runner.transcript(),
+            [
+                "query: git --version",
+                "query: dpkg -s git-all",
+                "run: sudo apt-get remove git-all",
+                "query: git --version",
+            ]
+        );
+        assert!(matches!(outcome, Outcome::Failed(_)), "{outcome:?}");
+    }
+
+    #[test]
+    fn absent_tool_that_is_still_found_fails() {
+        let facts = Facts::fixture(DEBIAN);
+        let registry = Registry::builtin().unwrap();
+        // git without git-all, which apt-get would "remove" successfully
+        let mut runner = RecordingRunner::new(
+            ScriptedRunner::new().on_query("git --version", "git version 2.39.5\n"),
+        );
+        let mut state_file = StateFile::empty();
+
+        let outcome = target_tool(
+            &mut runner,
+            &registry,
+            &facts,
+            &mut state_file,
+            "git",
+            absent(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            runner.transcript(),
+            [
+                "query: git --version",
+                "query: dpkg -s git-all",
+                "query: git --version",
+            ]
+        );
+        assert_eq!(
+            outcome,
+            Outcome::Failed(
+                "still installed after removing it, it may have been installed another way"
+                    .to_string()
+            )
+        );
+    }
+}
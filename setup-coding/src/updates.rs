@@ -0,0 +1,257 @@
+use crate::error::Error;
+use crate::facts::Facts;
+use crate::package_manager::{PackageManager, PackageNames};
+use crate::progress;
+use crate::report::Outcome;
+use crate::run_step;
+use crate::runner::Runner;
+
+/// The updates, in the order they run unless prerequisites say otherwise.
+pub const UPDATE_NAMES: &[&str] = &["system", "dependencies", "cleanup"];
+
+/// The development libraries and tools installed by the `dependencies` update.
+const DEPENDENCIES: &[PackageNames] = &[
+    PackageNames {
+        apk: &[],
+        apt: &["apt-transport-https"],
+        dnf: &[],
+        pacman: &[],
+    },
+    PackageNames {
+        apk: &["build-base"],
+        apt: &["build-essential"],
+        dnf: &["gcc", "gcc-c++", "make"],
+        pacman: &["base-devel"],
+    },
+    PackageNames {
+        apk: &["ca-certificates"],
+        apt: &["ca-certificates"],
+        dnf: &["ca-certificates"],
+        pacman: &["ca-certificates"],
+    },
+    PackageNames {
+        apk: &["clang"],
+        apt: &["clang"],
+        dnf: &["clang"],
+        pacman: &["clang"],
+    },
+    PackageNames {
+        apk: &["cmake"],
+        apt: &["cmake"],
+        dnf: &["cmake"],
+        pacman: &["cmake"],
+    },
+    PackageNames {
+        apk: &["curl"],
+        apt: &["curl"],
+        dnf: &["curl"],
+        pacman: &["curl"],
+    },
+    PackageNames {
+        apk: &["gnupg"],
+        apt: &["gnupg"],
+        dnf: &["gnupg2"],
+        pacman: &["gnupg"],
+    },
+    PackageNames {
+        apk: &["libappindicator-dev"],
+        apt: &["libappindicator3-dev"],
+        dnf: &["libappindicator-gtk3-devel"],
+        pacman: &["libappindicator-gtk3"],
+    },
+    PackageNames {
+        apk: &["alsa-lib-dev"],
+        apt: &["libasound2-dev"],
+        dnf: &["alsa-lib-devel"],
+        pacman: &["alsa-lib"],
+    },
+    PackageNames {
+        apk: &["mesa-dev"],
+        apt: &["libgl1-mesa-dev"],
+        dnf: &["mesa-libGL-devel"],
+        pacman: &["mesa"],
+    },
+    PackageNames {
+        apk: &["gtk+3.0-dev"],
+        apt: &["libgtk-3-dev"],
+        dnf: &["gtk3-devel"],
+        pacman: &["gtk3"],
+    },
+    PackageNames {
+        apk: &["libpq-dev"],
+        apt: &["libpq-dev"],
+        dnf: &["libpq-devel"],
+        pacman: &["postgresql-libs"],
+    },
+    PackageNames {
+        apk: &["openssl-dev"],
+        apt: &["libssl-dev"],
+        dnf: &["openssl-devel"],
+        pacman: &["openssl"],
+    },
+    PackageNames {
+        apk: &["eudev-dev"],
+        apt: &["libudev-dev"],
+        dnf: &["systemd-devel"],
+        pacman: &["systemd-libs"],
+    },
+    PackageNames {
+        apk: &["vulkan-loader-dev"],
+        apt: &["libvulkan-dev"],
+        dnf: &["vulkan-loader-devel"],
+        pacman: &["vulkan-icd-loader"],
+    },
+    PackageNames {
+        apk: &["wayland-dev"],
+        apt: &["libwayland-dev"],
+        dnf: &["wayland-devel"],
+        pacman: &["wayland"],
+    },
+    PackageNames {
+        apk: &["webkit2gtk-dev"],
+        apt: &["libwebkit2gtk-4.0-dev"],
+        dnf: &["webkit2gtk4.0-devel"],
+        pacman: &["webkit2gtk"],
+    },
+    PackageNames {
+        apk: &["libx11-dev"],
+        apt: &["libx11-dev"],
+        dnf: &["libX11-devel"],
+        pacman: &["libx11"],
+    },
+    PackageNames {
+        apk: &["libxi-dev"],
+        apt: &["libxi-dev"],
+        dnf: &["libXi-devel"],
+        pacman: &["libxi"],
+    },
+    PackageNames {
+        apk: &["libxcb-dev"],
+        apt: &["libxcb-xfixes0-dev", "libxcb-shape0-dev"],
+        dnf: &["libxcb-devel"],
+        pacman: &["libxcb"],
+    },
+    PackageNames {
+        apk: &["libxkbcommon-dev"],
+        apt: &["libxkbcommon-dev"],
+        dnf: &["libxkbcommon-devel"],
+        pacman: &["libxkbcommon"],
+    },
+    PackageNames {
+        apk: &["lld"],
+        apt: &["lld"],
+        dnf: &["lld"],
+        pacman: &["lld"],
+    },
+    PackageNames {
+        apk: &[],
+        apt: &["lsb-release"],
+        dnf: &["lsb_release"],
+        pacman: &["lsb-release"],
+    },
+    // alpine is built on musl already
+    PackageNames {
+        apk: &[],
+        apt: &["musl-tools"],
+        dnf: &["musl-gcc"],
+        pacman: &["musl"],
+    },
+    PackageNames {
+        apk: &["nginx"],
+        apt: &["nginx"],
+        dnf: &["nginx"],
+        pacman: &["nginx"],
+    },
+    PackageNames {
+        apk: &["pkgconf"],
+        apt: &["pkg-config"],
+        dnf: &["pkgconf-pkg-config"],
+        pacman: &["pkgconf"],
+    },
+    PackageNames {
+        apk: &["postgresql-client"],
+        apt: &["postgresql-client"],
+        dnf: &["postgresql"],
+        pacman: &["postgresql"],
+    },
+    PackageNames {
+        apk: &["python3"],
+        apt: &["python"],
+        dnf: &["python3"],
+        pacman: &["python"],
+    },
+];
+
+/// Runs one update, or skips it when the config turns it off.
+pub fn target_update(runner: &mut dyn Runner, name: &str, enabled: bool, facts: &Facts) -> Outcome {
+    if !enabled {
+        return Outcome::Skipped("disabled".to_string());
+    }
+
+    let result = match name {
+        "system" => update_system(runner, facts),
+        "dependencies" => update_dependencies(runner, facts),
+        "cleanup" => update_cleanup(runner, facts),
+        _ => Err(Error::InvalidConfig(format!("unknown update: {name}"))),
+    };
+
+    match result {
+        Ok(()) if runner.is_dry_run() => Outcome::Succeeded("dry run".to_string()),
+        Ok(()) => Outcome::Succeeded("updated".to_string()),
+        Err(error) => Outcome::Failed(error.to_string()),
+    }
+}
+
+pub fn update_system(runner: &mut dyn Runner, facts: &Facts) -> Result<(), Error> {
+    progress!("\nupdating system");
+
+    // sudo apt-get update
+    run_step(
+        runner,
+        "system updated",
+        &[facts.package_manager()?.update()],
+    )?;
+
+    Ok(())
+}
+
+/// The dependency list under the names `package_manager` knows the packages by.
+pub fn get_dependency_packages(package_manager: PackageManager) -> Vec<&'static str> {
+    let mut packages: Vec<&str> = Vec::new();
+
+    for names in DEPENDENCIES {
+        for name in package_manager.package_names(names) {
+            if !packages.contains(name) {
+                packages.push(name);
+            }
+        }
+    }
+
+    packages
+}
+
+pub fn update_dependencies(runner: &mut dyn Runner, facts: &Facts) -> Result<(), Error> {
+    let package_manager = facts.package_manager()?;
+    let packages = get_dependency_packages(package_manager);
+
+    // sudo apt-get install apt-transport-https build-essential ca-certificates ...
+    run_step(
+        runner,
+        "installed dependencies",
+        &[package_manager.install(&packages)],
+    )?;
+
+    Ok(())
+}
+
+pub fn update_cleanup(runner: &mut dyn Runner, facts: &Facts) -> Result<(), Error> {
+    let package_manager = facts.package_manager()?;
+
+    match package_manager.cleanup() {
+        None => progress!("nothing to clean up with {package_manager}"),
+        // sudo apt-get autoremove
+        Some(cleanup) => run_step(runner, "system cleaned up", &[cleanup])?,
+    }
+
+    Ok(())
+}
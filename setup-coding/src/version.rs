@@ -0,0 +1,138 @@
+use regex::Regex;
+use semver::{Op, Version, VersionReq};
+
+/// Finds a version in the output of a tool, using the first capture group of `pattern`.
+///
+/// Versions without a patch number, like `1.10`, are read as `1.10.0`.
+pub fn parse_version(output: &str, pattern: &Regex) -> Option<Version> {
+    let text = pattern.captures(output)?.get(1)?.as_str();
+    let core_length = text.find(['-', '+']).unwrap_or(text.len());
+
+    if text[..core_length].matches('.').count() == 1 {
+        let (core, rest) = text.split_at(core_length);
+
+        Version::parse(&format!("{core}.0{rest}")).ok()
+    } else {
+        Version::parse(text).ok()
+    }
+}
+
+/// Picks the version to install for a requirement: the lowest version its first comparator
+/// allows, as long as the whole requirement accepts it.
+///
+/// A bare version like `1.29.2` is a caret requirement, just like in Cargo, so it installs
+/// `1.29.2` and is satisfied by any later `1.x` release. Releases are not looked up, so an
+/// open requirement like `>=2` installs `2.0.0` rather than the latest release; pin the
+/// version with `=` to get a later one. Requirements without a lowest version, like `<2`,
+/// have none.
+pub fn install_version(requirement: &VersionReq) -> Option<Version> {
+    let comparator = requirement.comparators.first()?;
+
+    match comparator.op {
+        Op::Exact | Op::GreaterEq | Op::Tilde | Op::Caret | Op::Wildcard => {}
+        _ => return None,
+    }
+
+    let mut version = Version::new(
+        comparator.major,
+        comparator.minor.unwrap_or(0),
+        comparator.patch.unwrap_or(0),
+    );
+    version.pre = comparator.pre.clone();
+
+    if requirement.matches(&version) {
+        Some(version)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The pattern of the built-in recipes, after whatever precedes the version.
+    const VERSION: &str = r"(\d+\.\d+(?:\.\d+)?(?:-[0-9A-Za-z.]+)?)";
+
+    fn parse(output: &str, prefix: &str) -> Option<Version> {
+        let pattern = Regex::new(&format!("{prefix}{VERSION}")).unwrap();
+
+        parse_version(output, &pattern)
+    }
+
+    #[test]
+    fn versions_are_read_from_tool_output() {
+        let cases = [
+            ("v18.0.1\n", "^v", Some("18.0.1")),
+            (
+                "solana-cli 1.10.8 (src:93e3dd0e; feat:3488713414)\n",
+                "solana-cli ",
+                Some("1.10.8"),
+            ),
+            (
+                "docker-compose version 1.29.2, build 5becea4c\n",
+                "version v?",
+                Some("1.29.2"),
+            ),
+            (
+                "Docker Compose version v2.20.2\n",
+                "version v?",
+                Some("2.20.2"),
+            ),
+            ("jq-1.10\n", "jq-", Some("1.10.0")),
+            ("tool 1.2-beta.1\n", "tool ", Some("1.2.0-beta.1")),
+            ("tool 11.1.0\n", "tool ", Some("11.1.0")),
+            ("tool version unknown\n", "tool ", None),
+            ("v18.0.1\n", "^node ", None),
+        ];
+
+        for (output, prefix, expected) in cases {
+            let expected = expected.map(|version| Version::parse(version).unwrap());
+
+            assert_eq!(parse(output, prefix), expected, "{output:?}");
+        }
+    }
+
+    #[test]
+    fn requirements_compare_whole_numbers() {
+        let installed = parse("tool 11.1.0\n", "tool ").unwrap();
+
+        for requirement in ["1.1", "=1.1", "^1.1", "~1.1"] {
+            let requirement = VersionReq::parse(requirement).unwrap();
+
+            assert!(!requirement.matches(&installed), "{requirement}");
+        }
+
+        assert!(VersionReq::parse("^11.1").unwrap().matches(&installed));
+    }
+
+    #[test]
+    fn install_version_is_the_lowest_allowed() {
+        let cases = [
+            ("=1.29.2", Some("1.29.2")),
+            ("1.29.2", Some("1.29.2")),
+            ("^18", Some("18.0.0")),
+            ("~1.10", Some("1.10.0")),
+            ("1.10", Some("1.10.0")),
+            ("1.*", Some("1.0.0")),
+            ("=2.0.0-rc.1", Some("2.0.0-rc.1")),
+            (">=1.10.8, <1.11", Some("1.10.8")),
+            // no releases are looked up, so this is not the latest 2.x
+            (">=2", Some("2.0.0")),
+            ("<2", None),
+            (">1.0", None),
+            ("*", None),
+            (">=2, <1", None),
+        ];
+
+        for (requirement, expected) in cases {
+            let expected = expected.map(|version| Version::parse(version).unwrap());
+
+            assert_eq!(
+                install_version(&VersionReq::parse(requirement).unwrap()),
+                expected,
+                "{requirement}"
+            );
+        }
+    }
+}